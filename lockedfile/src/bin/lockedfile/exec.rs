@@ -0,0 +1,123 @@
+use std::ffi::OsString;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+use lockedfile::LockMode;
+use lockedfile::std::OpenOptions;
+
+struct Args {
+    mode: LockMode,
+    nonblock: bool,
+    timeout: Option<Duration>,
+    conflict_exit_code: i32,
+    path: PathBuf,
+    command: Vec<OsString>,
+}
+
+pub fn run(args: &[OsString]) -> i32 {
+    let args = match parse(args) {
+        Ok(args) => args,
+        Err(message) => return super::usage_error(&message),
+    };
+
+    let mut options = OpenOptions::new();
+    options.read(true);
+    // A shared lock on an existing file only needs read access, like flock(1).
+    if args.mode == LockMode::Exclusive || !args.path.exists() {
+        options.write(true).create(true).truncate(false);
+    }
+
+    let file = if args.nonblock {
+        options.try_open_with(&args.path, args.mode)
+            .and_then(|file| file.ok_or_else(|| std::io::Error::new(
+                std::io::ErrorKind::WouldBlock,
+                format!("{} is locked", args.path.display()))))
+    } else if let Some(timeout) = args.timeout {
+        options.open_timeout(&args.path, args.mode, timeout)
+    } else {
+        options.open_with(&args.path, args.mode)
+    };
+    let file = match file {
+        Ok(file) => file,
+        Err(e) if is_conflict(&e) => {
+            eprintln!("lockedfile: {}", e);
+            return args.conflict_exit_code;
+        },
+        Err(e) => return super::error(e),
+    };
+
+    // The command inherits the locked file, like with flock(1), so that the lock
+    // stays held until the command exits even if this process is killed.
+    if let Err(e) = inherit(&file) {
+        return super::error(e);
+    }
+
+    let status = Command::new(&args.command[0])
+        .args(&args.command[1..])
+        .status();
+    drop(file);
+
+    match status {
+        Ok(status) => status.code().unwrap_or_else(|| 128 + status.signal().unwrap_or(0)),
+        Err(e) => {
+            eprintln!("lockedfile: {}: {}", args.command[0].to_string_lossy(), e);
+            // The statuses a shell uses for commands it cannot find or run.
+            if e.kind() == std::io::ErrorKind::NotFound { 127 } else { 126 }
+        },
+    }
+}
+
+fn is_conflict(e: &std::io::Error) -> bool {
+    matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut)
+}
+
+fn inherit(file: &std::fs::File) -> std::io::Result<()> {
+    let fd = file.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFD) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFD, flags & !libc::FD_CLOEXEC) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+fn parse(args: &[OsString]) -> Result<Args, String> {
+    let mut mode = LockMode::Exclusive;
+    let mut nonblock = false;
+    let mut timeout = None;
+    let mut conflict_exit_code = 1;
+    let mut path = None;
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.to_str() {
+            Some("-s") | Some("--shared") => mode = LockMode::Shared,
+            Some("-x") | Some("--exclusive") => mode = LockMode::Exclusive,
+            Some("-n") | Some("--nonblock") => nonblock = true,
+            Some("-w") | Some("--timeout") => {
+                let value = super::option_value("--timeout", &mut iter)?;
+                timeout = Some(super::parse_duration(value)?);
+            },
+            Some("-E") | Some("--conflict-exit-code") => {
+                let value = super::option_value("--conflict-exit-code", &mut iter)?;
+                conflict_exit_code = value.parse::<u8>()
+                    .map_err(|_| format!("invalid exit code '{}'", value))?
+                    .into();
+            },
+            Some("--") if path.is_some() => break,
+            Some(option) if option.starts_with('-') => return Err(format!("unknown option '{}'", option)),
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => return Err("expected '--' before the command".to_string()),
+        }
+    }
+
+    let path = path.ok_or("missing PATH")?;
+    let command = iter.cloned().collect::<Vec<_>>();
+    if command.is_empty() {
+        return Err("missing command to run".to_string());
+    }
+
+    Ok(Args { mode, nonblock, timeout, conflict_exit_code, path, command })
+}
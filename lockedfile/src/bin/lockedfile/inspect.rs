@@ -0,0 +1,290 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use lockedfile::LockMode;
+use lockedfile::std::OpenOptions;
+use super::json_string;
+
+/// A lock on the inspected file, as reported by the kernel.
+struct Lock {
+    pid: Option<u32>,
+    mode: LockMode,
+    kind: String,
+    /// The process is blocked waiting for the lock rather than holding it.
+    waiting: bool,
+}
+
+struct Args {
+    path: PathBuf,
+    json: bool,
+    shared: bool,
+}
+
+pub fn status(args: &[OsString]) -> i32 {
+    let args = match parse(args, false) {
+        Ok(args) => args,
+        Err(message) => return super::usage_error(&message),
+    };
+
+    // Holder and waiter counts are only known where the kernel lists locks.
+    let (mode, counts) = match locks(&args.path).and_then(|locks| match locks {
+        Some(locks) => {
+            let holders = locks.iter().filter(|lock| !lock.waiting).collect::<Vec<_>>();
+            let mode = if holders.is_empty() {
+                None
+            } else if holders.iter().any(|lock| lock.mode == LockMode::Exclusive) {
+                Some(LockMode::Exclusive)
+            } else {
+                Some(LockMode::Shared)
+            };
+            Ok((mode, Some((holders.len(), locks.len() - holders.len()))))
+        },
+        None => probe(&args.path).map(|mode| (mode, None)),
+    }) {
+        Ok(status) => status,
+        Err(e) => return super::error(e),
+    };
+
+    let path = args.path.to_string_lossy();
+    if args.json {
+        let count = |count: Option<usize>| count.map_or_else(|| "null".to_string(), |count| count.to_string());
+        println!("{{\"path\":{},\"locked\":{},\"mode\":{},\"holders\":{},\"waiting\":{}}}",
+            json_string(&path),
+            mode.is_some(),
+            mode.map_or_else(|| "null".to_string(), |mode| json_string(mode_name(mode))),
+            count(counts.map(|(holders, _)| holders)),
+            count(counts.map(|(_, waiting)| waiting)));
+    } else {
+        let state = match (mode, counts) {
+            (Some(mode), Some((holders, waiting))) => format!("locked {} ({} holding, {} waiting)", mode_name(mode), holders, waiting),
+            (Some(mode), None) => format!("locked {}", mode_name(mode)),
+            (None, Some((_, waiting))) if waiting > 0 => format!("unlocked ({} waiting)", waiting),
+            (None, _) => "unlocked".to_string(),
+        };
+        println!("{}: {}", path, state);
+    }
+
+    0
+}
+
+pub fn who(args: &[OsString]) -> i32 {
+    let args = match parse(args, false) {
+        Ok(args) => args,
+        Err(message) => return super::usage_error(&message),
+    };
+    let locks = match locks(&args.path).and_then(|locks| match locks {
+        Some(locks) => Ok(locks),
+        None => conflicting_lock(&args.path).map(|lock| lock.into_iter().collect()),
+    }) {
+        Ok(locks) => locks,
+        Err(e) => return super::error(e),
+    };
+
+    if args.json {
+        let locks = locks.iter().map(|lock| {
+            format!("{{\"pid\":{},\"mode\":{},\"type\":{},\"waiting\":{},\"command\":{}}}",
+                lock.pid.map_or_else(|| "null".to_string(), |pid| pid.to_string()),
+                json_string(mode_name(lock.mode)),
+                json_string(&lock.kind),
+                lock.waiting,
+                lock.pid.and_then(command_line).map_or_else(|| "null".to_string(), |command| json_string(&command)))
+        }).collect::<Vec<_>>();
+        println!("{{\"path\":{},\"locks\":[{}]}}", json_string(&args.path.to_string_lossy()), locks.join(","));
+    } else if locks.is_empty() {
+        println!("{}: not locked", args.path.display());
+    } else {
+        println!("{:<8} {:<10} {:<7} {:<8} COMMAND", "PID", "MODE", "TYPE", "STATE");
+        for lock in &locks {
+            println!("{:<8} {:<10} {:<7} {:<8} {}",
+                lock.pid.map_or_else(|| "-".to_string(), |pid| pid.to_string()),
+                mode_name(lock.mode),
+                lock.kind,
+                if lock.waiting { "waiting" } else { "holding" },
+                lock.pid.and_then(command_line).unwrap_or_default());
+        }
+    }
+
+    0
+}
+
+pub fn wait(args: &[OsString]) -> i32 {
+    let args = match parse(args, true) {
+        Ok(args) => args,
+        Err(message) => return super::usage_error(&message),
+    };
+    let mode = if args.shared { LockMode::Shared } else { LockMode::Exclusive };
+
+    // A file that does not exist cannot be locked by anyone, and is not created.
+    let start = Instant::now();
+    if args.path.exists() {
+        let result = OpenOptions::new()
+            .read(true)
+            .write(mode == LockMode::Exclusive)
+            .open_with(&args.path, mode)
+            .or_else(|e| match e.kind() {
+                // flock does not need write access for an exclusive lock.
+                std::io::ErrorKind::PermissionDenied => OpenOptions::new().read(true).open_with(&args.path, mode),
+                _ => Err(e),
+            });
+        if let Err(e) = result {
+            return super::error(e);
+        }
+    }
+    let waited = start.elapsed().as_secs_f64();
+
+    let path = args.path.to_string_lossy();
+    if args.json {
+        println!("{{\"path\":{},\"mode\":{},\"waited\":{:.3}}}", json_string(&path), json_string(mode_name(mode)), waited);
+    } else {
+        println!("{}: can be locked {} (waited {:.3}s)", path, mode_name(mode), waited);
+    }
+
+    0
+}
+
+fn parse(args: &[OsString], allow_shared: bool) -> Result<Args, String> {
+    let mut path = None;
+    let mut json = false;
+    let mut shared = false;
+
+    for arg in args {
+        match arg.to_str() {
+            Some("--json") => json = true,
+            Some("-s") | Some("--shared") if allow_shared => shared = true,
+            Some(option) if option.starts_with('-') => return Err(format!("unknown option '{}'", option)),
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => return Err("expected a single PATH".to_string()),
+        }
+    }
+
+    Ok(Args {
+        path: path.ok_or("missing PATH")?,
+        json,
+        shared,
+    })
+}
+
+fn mode_name(mode: LockMode) -> &'static str {
+    match mode {
+        LockMode::Shared => "shared",
+        LockMode::Exclusive => "exclusive",
+    }
+}
+
+/// Lists the locks on `path` from `/proc/locks`, or returns None where the kernel
+/// does not list locks.
+fn locks(path: &Path) -> std::io::Result<Option<Vec<Lock>>> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        let meta = std::fs::metadata(path)?;
+        match std::fs::read_to_string("/proc/locks") {
+            Ok(table) => return Ok(Some(parse_proc_locks(&table, meta.dev(), meta.ino()))),
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            Err(_) => {},
+        }
+    }
+
+    std::fs::metadata(path)?;
+
+    Ok(None)
+}
+
+/// Parses lines like `1: FLOCK  ADVISORY  WRITE 1234 fe:00:1220656 0 EOF`, where
+/// waiters are marked by `->` after the number.
+#[cfg(target_os = "linux")]
+fn parse_proc_locks(table: &str, dev: u64, ino: u64) -> Vec<Lock> {
+    use std::convert::TryFrom;
+
+    let id = format!("{:02x}:{:02x}:{}", libc::major(dev), libc::minor(dev), ino);
+
+    table.lines().filter_map(|line| {
+        let mut fields = line.split_whitespace().skip(1).peekable();
+        let waiting = fields.next_if_eq(&"->").is_some();
+        let kind = fields.next()?;
+        let _ = fields.next()?;
+        let mode = match fields.next()? {
+            "READ" => LockMode::Shared,
+            "WRITE" => LockMode::Exclusive,
+            _ => return None,
+        };
+        let pid = fields.next()?.parse::<i64>().ok()?;
+        if fields.next()? != id || !matches!(kind, "FLOCK" | "POSIX" | "OFDLCK") {
+            return None;
+        }
+
+        Some(Lock {
+            // Open file description locks are not owned by a process.
+            pid: u32::try_from(pid).ok().filter(|&pid| pid > 0),
+            mode,
+            kind: kind.to_string(),
+            waiting,
+        })
+    }).collect()
+}
+
+/// Asks the kernel with `F_GETLK` for a lock that keeps `path` from being locked
+/// exclusively, where it does not list locks. That reports at most one holder and no
+/// waiters. On BSD and macOS, `flock` and record locks share one implementation, so
+/// it sees the locks lockedfile takes; on Linux it only sees record locks.
+fn conflicting_lock(path: &Path) -> std::io::Result<Option<Lock>> {
+    use std::convert::TryFrom;
+    use std::os::unix::io::AsRawFd;
+
+    let file = std::fs::File::open(path)?;
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let mode = match lock.l_type as libc::c_int {
+        libc::F_UNLCK => return Ok(None),
+        libc::F_RDLCK => LockMode::Shared,
+        _ => LockMode::Exclusive,
+    };
+
+    Ok(Some(Lock {
+        // flock locks have no owning process on some systems.
+        pid: u32::try_from(lock.l_pid).ok().filter(|&pid| pid > 0),
+        mode,
+        // F_GETLK does not tell flock and record locks apart.
+        kind: "UNKNOWN".to_string(),
+        waiting: false,
+    }))
+}
+
+/// Tells how `path` is locked by taking a lock without waiting and releasing it
+/// right away, on systems whose kernel does not list locks.
+fn probe(path: &Path) -> std::io::Result<Option<LockMode>> {
+    let mut options = OpenOptions::new();
+    options.read(true);
+
+    if options.try_open_exclusive(path)?.is_some() {
+        Ok(None)
+    } else if options.try_open_shared(path)?.is_some() {
+        Ok(Some(LockMode::Shared))
+    } else {
+        Ok(Some(LockMode::Exclusive))
+    }
+}
+
+fn command_line(pid: u32) -> Option<String> {
+    if let Ok(cmdline) = std::fs::read(format!("/proc/{}/cmdline", pid)) {
+        let args = cmdline.split(|&byte| byte == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(String::from_utf8_lossy)
+            .collect::<Vec<_>>();
+        return Some(args.join(" ")).filter(|command| !command.is_empty());
+    }
+
+    let output = std::process::Command::new("ps")
+        .args(["-o", "command=", "-p", &pid.to_string()])
+        .output()
+        .ok()?;
+    let command = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+    Some(command).filter(|command| output.status.success() && !command.is_empty())
+}
@@ -0,0 +1,98 @@
+use std::ffi::OsString;
+use std::time::Duration;
+
+mod exec;
+mod inspect;
+
+const USAGE: &str = "\
+usage: lockedfile exec [--shared|--exclusive] [--nonblock] [--timeout DURATION]
+                       [--conflict-exit-code CODE] PATH -- COMMAND [ARGS...]
+       lockedfile status [--json] PATH
+       lockedfile who [--json] PATH
+       lockedfile wait [--shared] [--json] PATH
+
+Runs COMMAND while holding a lock on PATH, which is created if it does not exist,
+and exits with the status of COMMAND. The lock is exclusive unless --shared is given.
+COMMAND inherits the locked file, so the lock is held until COMMAND and any process
+it leaves behind with the file open exit. With --nonblock or --timeout, exits with
+status 1, or CODE, if the lock cannot be taken. DURATION is a number of seconds,
+optionally suffixed with ms, s, m or h.
+
+status tells whether PATH is locked and in which mode, and who lists the processes
+holding or waiting for locks on it. Both read /proc/locks without taking a lock.
+Where it does not exist, status briefly takes a lock to find out, and who asks the
+kernel with F_GETLK, which reports one holder at most and no waiters.
+wait blocks until PATH could be locked exclusively, or shared with --shared, and
+exits without locking it.";
+
+/// Exit status for invalid arguments, as in sysexits.h.
+const EX_USAGE: i32 = 64;
+
+fn main() {
+    let args = std::env::args_os().skip(1).collect::<Vec<_>>();
+
+    let code = match args.first().and_then(|arg| arg.to_str()) {
+        Some("exec") => exec::run(&args[1..]),
+        Some("status") => inspect::status(&args[1..]),
+        Some("who") => inspect::who(&args[1..]),
+        Some("wait") => inspect::wait(&args[1..]),
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            0
+        },
+        Some(command) => usage_error(&format!("unknown command '{}'", command)),
+        None => usage_error("missing command"),
+    };
+
+    std::process::exit(code);
+}
+
+fn usage_error(message: &str) -> i32 {
+    eprintln!("lockedfile: {}\n{}", message, USAGE);
+
+    EX_USAGE
+}
+
+fn error(e: std::io::Error) -> i32 {
+    eprintln!("lockedfile: {}", e);
+
+    1
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+
+    json
+}
+
+fn option_value<'a>(name: &str, args: &mut impl Iterator<Item = &'a OsString>) -> Result<&'a str, String> {
+    args.next()
+        .and_then(|value| value.to_str())
+        .ok_or_else(|| format!("{} needs a value", name))
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (value, unit) = s.split_at(split);
+    let scale = match unit {
+        "ms" => 0.001,
+        "" | "s" => 1.0,
+        "m" => 60.0,
+        "h" => 3600.0,
+        _ => return Err(format!("invalid duration '{}'", s)),
+    };
+
+    value.parse::<f64>().ok()
+        .and_then(|value| Duration::try_from_secs_f64(value * scale).ok())
+        .ok_or_else(|| format!("invalid duration '{}'", s))
+}
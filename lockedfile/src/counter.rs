@@ -0,0 +1,85 @@
+use std::ops::Range;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use crate::std::OpenOptions;
+
+/// Enough digits for `u64::MAX`.
+const WIDTH: usize = 20;
+
+/// A 64-bit counter in a file, shared between processes.
+///
+/// The value is stored as 20 decimal digits and a newline, and every update is a
+/// read-modify-write under an exclusive lock on the file. A missing or empty file
+/// counts as zero. `next` hands out 1, 2, 3, ...; `reserve` hands out a whole range
+/// at once, for callers that would rather not lock on every increment.
+pub struct Counter {
+    path: PathBuf,
+}
+
+impl Counter {
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open_shared(&path)?;
+        read(&file, &path)?;
+
+        Ok(Self { path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get(&self) -> std::io::Result<u64> {
+        let file = OpenOptions::new()
+            .read(true)
+            .open_shared(&self.path)?;
+
+        read(&file, &self.path)
+    }
+
+    /// Increments the counter and returns the new value.
+    pub fn next(&self) -> std::io::Result<u64> {
+        self.add(1)
+    }
+
+    /// Adds `n` to the counter and returns the new value.
+    pub fn add(&self, n: u64) -> std::io::Result<u64> {
+        self.reserve(n).map(|range| range.end - 1)
+    }
+
+    /// Reserves the next `n` values, which would otherwise be returned by `n` calls to `next`.
+    pub fn reserve(&self, n: u64) -> std::io::Result<Range<u64>> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open_exclusive(&self.path)?;
+
+        let value = read(&file, &self.path)?;
+        // `u64::MAX` itself is never reached, so that the returned range stays representable.
+        let new_value = value.checked_add(n).filter(|&v| v < u64::MAX).ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "counter overflow")
+        })?;
+        file.write_all_at(format!("{:0width$}\n", new_value, width = WIDTH).as_bytes(), 0)?;
+
+        Ok(value + 1..new_value + 1)
+    }
+}
+
+fn read(file: &std::fs::File, path: &Path) -> std::io::Result<u64> {
+    let mut buf = [0u8; WIDTH + 1];
+    let len = file.read_at(&mut buf, 0)?;
+    if len == 0 {
+        return Ok(0);
+    }
+
+    std::str::from_utf8(&buf[..len])
+        .ok()
+        .and_then(|s| s.trim_end().parse().ok())
+        .ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("not a counter: {}", path.display())))
+}
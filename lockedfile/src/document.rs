@@ -0,0 +1,126 @@
+use std::io::Write;
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use crate::std::{AtomicWriter, OpenOptions};
+
+/// The serialization format of a `Document`; each one is behind a feature of the same name.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Format {
+    #[cfg(feature = "json")]
+    Json,
+    #[cfg(feature = "toml")]
+    Toml,
+    #[cfg(feature = "bincode")]
+    Bincode,
+}
+
+impl Format {
+    fn serialize<T: Serialize>(self, value: &T) -> std::io::Result<Vec<u8>> {
+        match self {
+            #[cfg(feature = "json")]
+            Format::Json => serde_json::to_vec_pretty(value).map_err(invalid_data),
+            #[cfg(feature = "toml")]
+            Format::Toml => toml::to_string(value).map(String::into_bytes).map_err(invalid_data),
+            #[cfg(feature = "bincode")]
+            Format::Bincode => bincode::serialize(value).map_err(invalid_data),
+        }
+    }
+
+    fn deserialize<T: DeserializeOwned>(self, buf: &[u8]) -> std::io::Result<T> {
+        match self {
+            #[cfg(feature = "json")]
+            Format::Json => serde_json::from_slice(buf).map_err(invalid_data),
+            #[cfg(feature = "toml")]
+            Format::Toml => {
+                let s = std::str::from_utf8(buf).map_err(invalid_data)?;
+                toml::from_str(s).map_err(invalid_data)
+            },
+            #[cfg(feature = "bincode")]
+            Format::Bincode => bincode::deserialize(buf).map_err(invalid_data),
+        }
+    }
+}
+
+/// A value of type `T` stored in a file that several processes share.
+///
+/// `load` reads the file under a shared lock, `store` replaces it and `update`
+/// does a read-modify-write under an exclusive lock. Writes go through
+/// `AtomicWriter`, so the lock is taken on the sidecar `<name>.lock` and readers
+/// never see a partially written document.
+pub struct Document<T> {
+    path: PathBuf,
+    format: Format,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T: Serialize + DeserializeOwned> Document<T> {
+    pub fn new<P: AsRef<Path>>(path: P, format: Format) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            format,
+            _value: PhantomData,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Fails with `ErrorKind::NotFound` if the document was never stored.
+    pub fn load(&self) -> std::io::Result<T> {
+        let _lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open_shared(crate::std::atomic::lock_path(&self.path))?;
+
+        self.read()
+    }
+
+    pub fn store(&self, value: &T) -> std::io::Result<()> {
+        let writer = AtomicWriter::new(&self.path)?;
+
+        self.write(writer, value)
+    }
+
+    /// Applies `f` to the current value, or to `T::default()` if the document was
+    /// never stored, and stores the result unless `f` panics.
+    pub fn update<F, R>(&self, f: F) -> std::io::Result<R>
+    where
+        T: Default,
+        F: FnOnce(&mut T) -> R,
+    {
+        let writer = AtomicWriter::new(&self.path)?;
+        let mut value = match self.read() {
+            Ok(value) => value,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => T::default(),
+            Err(e) => return Err(e),
+        };
+
+        let result = f(&mut value);
+        self.write(writer, &value)?;
+
+        Ok(result)
+    }
+
+    fn read(&self) -> std::io::Result<T> {
+        let buf = std::fs::read(&self.path)?;
+
+        self.format.deserialize(&buf)
+    }
+
+    fn write(&self, mut writer: AtomicWriter, value: &T) -> std::io::Result<()> {
+        writer.write_all(&self.format.serialize(value)?)?;
+
+        writer.commit()
+    }
+}
+
+fn invalid_data<E: std::error::Error + Send + Sync + 'static>(e: E) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+}
@@ -0,0 +1,40 @@
+use std::os::unix::fs::{FileExt, MetadataExt};
+use std::time::Duration;
+
+/// How long a campaign waits for the lock at a time before checking whether it was stopped.
+pub(crate) const WAIT_SLICE: Duration = Duration::from_millis(100);
+
+/// A change of leadership, reported by a campaign of `LeaderElection`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum ElectionEvent {
+    /// This candidate became the leader.
+    Gained,
+    /// This candidate stopped being the leader, because the election file was
+    /// removed or replaced behind its back.
+    Lost,
+}
+
+/// Writes `identity` as the first line of the election file.
+///
+/// The file is read without a lock, so the line is written with a single write
+/// before the rest of the file is cut off: readers see the previous content, or
+/// the complete line followed by what is left of a longer previous identity.
+pub(crate) fn write_identity(file: &std::fs::File, identity: &str) -> std::io::Result<()> {
+    let line = format!("{}\n", identity);
+    file.write_all_at(line.as_bytes(), 0)?;
+    file.set_len(line.len() as u64)?;
+
+    file.sync_data()
+}
+
+/// Parses the identity a leader wrote into the election file, from its first complete line.
+pub(crate) fn parse_identity(buf: &[u8]) -> Option<String> {
+    let end = buf.iter().position(|&byte| byte == b'\n')?;
+    let identity = String::from_utf8_lossy(&buf[..end]).trim_end().to_owned();
+
+    if identity.is_empty() { None } else { Some(identity) }
+}
+
+pub(crate) fn same_file(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
@@ -0,0 +1,69 @@
+const MAGIC: &[u8; 4] = b"LFC1";
+const HEADER_LEN: usize = MAGIC.len() + 8 + 4;
+
+/// Returned, wrapped in an `io::Error` of kind `InvalidData`, when framed content does
+/// not match the length or checksum stored in its header.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Corrupted {
+    pub reason: &'static str,
+}
+
+impl std::fmt::Display for Corrupted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "corrupted content: {}", self.reason)
+    }
+}
+
+impl std::error::Error for Corrupted {}
+
+impl From<Corrupted> for std::io::Error {
+    fn from(corrupted: Corrupted) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, corrupted)
+    }
+}
+
+/// Prefixes `content` with the magic `LFC1`, its length as a little-endian u64 and
+/// its CRC32C as a little-endian u32.
+pub(crate) fn encode(content: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + content.len());
+    buf.extend_from_slice(MAGIC);
+    buf.extend_from_slice(&(content.len() as u64).to_le_bytes());
+    buf.extend_from_slice(&crc32c(content).to_le_bytes());
+    buf.extend_from_slice(content);
+
+    buf
+}
+
+pub(crate) fn decode(mut buf: Vec<u8>) -> std::io::Result<Vec<u8>> {
+    if buf.len() < HEADER_LEN || &buf[..MAGIC.len()] != MAGIC {
+        return Err(Corrupted { reason: "missing header" }.into());
+    }
+
+    let mut len = [0u8; 8];
+    len.copy_from_slice(&buf[MAGIC.len()..MAGIC.len() + 8]);
+    let mut checksum = [0u8; 4];
+    checksum.copy_from_slice(&buf[MAGIC.len() + 8..HEADER_LEN]);
+
+    let content = buf.split_off(HEADER_LEN);
+    if content.len() as u64 != u64::from_le_bytes(len) {
+        return Err(Corrupted { reason: "length mismatch" }.into());
+    }
+    if crc32c(&content) != u32::from_le_bytes(checksum) {
+        return Err(Corrupted { reason: "checksum mismatch" }.into());
+    }
+
+    Ok(content)
+}
+
+/// CRC-32C (Castagnoli), computed bitwise.
+pub(crate) fn crc32c(buf: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in buf {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0x82f6_3b78 } else { crc >> 1 };
+        }
+    }
+
+    !crc
+}
@@ -0,0 +1,69 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The content of a lease file: who holds the lease and when it was last refreshed.
+#[derive(Debug,Clone,PartialEq)]
+pub struct LeaseRecord {
+    pub owner: String,
+    pub timestamp: SystemTime,
+}
+
+impl LeaseRecord {
+    pub(crate) fn new(owner: String) -> Self {
+        Self {
+            owner,
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    /// A record whose timestamp lies in the future is never considered expired.
+    pub fn is_expired(&self, ttl: Duration) -> bool {
+        match SystemTime::now().duration_since(self.timestamp) {
+            Ok(age) => age > ttl,
+            Err(_) => false,
+        }
+    }
+
+    pub(crate) fn from_bytes(buf: &[u8]) -> Option<Self> {
+        let s = std::str::from_utf8(buf).ok()?;
+        let mut lines = s.lines();
+        let owner = lines.next()?;
+        let millis = lines.next()?.parse::<u64>().ok()?;
+
+        if owner.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            owner: owner.to_owned(),
+            timestamp: UNIX_EPOCH + Duration::from_millis(millis),
+        })
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let millis = self.timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+
+        format!("{}\n{}\n", self.owner, millis).into_bytes()
+    }
+}
+
+pub(crate) fn refresh_interval(ttl: Duration) -> Duration {
+    ttl / 3
+}
+
+/// How long a holder trusts its lease after starting a refresh, which ends early by a
+/// margin for clock skew between the holder and the hosts that expire its record.
+pub(crate) fn hold_time(ttl: Duration) -> Duration {
+    ttl - ttl / 10
+}
+
+/// How long `try_acquire` waits before reading its record back.
+pub(crate) fn confirm_delay(ttl: Duration) -> Duration {
+    std::cmp::min(ttl / 10, Duration::from_millis(100))
+}
+
+pub(crate) fn poll_interval(ttl: Duration) -> Duration {
+    std::cmp::min(ttl / 4, Duration::from_secs(1))
+}
@@ -1,4 +1,59 @@
 pub mod std;
 pub mod tokio;
+mod counter;
+#[cfg(feature = "serde")]
+mod document;
+mod election;
+mod frame;
+mod lease;
+#[cfg(feature = "lockdep")]
+mod lockdep;
+mod lockset;
+mod probe;
+mod queue;
+mod semaphore;
+mod sys;
 #[macro_use]
 mod macros;
+
+pub use crate::counter::Counter;
+#[cfg(feature = "serde")]
+pub use crate::document::{Document, Format};
+pub use crate::frame::Corrupted;
+pub use crate::probe::{probe, Capabilities, Support, UnreliableLock};
+pub use crate::queue::{Claim, Queue};
+pub use crate::semaphore::{Permit, Semaphore};
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+/// How a file is flushed to disk before its exclusive lock is released.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum SyncPolicy {
+    #[default]
+    None,
+    /// `fdatasync`: the content and the metadata needed to read it back.
+    Data,
+    /// `fsync`: the content and all metadata.
+    All,
+}
+
+impl LockMode {
+    /// The `open(2)` flag that takes this lock while opening the file, on the platforms
+    /// that have one. Elsewhere, the lock is taken with `flock(2)` after opening.
+    #[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly"))]
+    pub(crate) fn open_flag(self) -> Option<i32> {
+        match self {
+            LockMode::Shared => Some(libc::O_SHLOCK),
+            LockMode::Exclusive => Some(libc::O_EXLOCK),
+        }
+    }
+
+    #[cfg(not(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd", target_os = "dragonfly")))]
+    pub(crate) fn open_flag(self) -> Option<i32> {
+        None
+    }
+}
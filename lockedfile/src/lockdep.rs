@@ -0,0 +1,188 @@
+//! Lock-order checking for the `lockdep` feature.
+//!
+//! Every lock taken through `lockedfile::std::OpenOptions` is recorded together
+//! with the locks the same thread already holds. Taking lock B while holding A
+//! adds the edge A -> B to a process-wide graph; an acquisition that would close
+//! a cycle is an order inversion and is reported. Reports panic unless the
+//! `LOCKEDFILE_LOCKDEP` environment variable is set to `log`.
+//!
+//! Non-blocking attempts cannot deadlock, so they are only recorded as held
+//! locks and add no edges. Locks taken through the tokio API are not tracked
+//! because tasks move between threads.
+//!
+//! Locks are identified by device and inode. An inode that was freed and reused
+//! for another file is told apart by its birth time, which drops the edges of the
+//! old file; where the filesystem does not report birth times, they are kept.
+//! Paths only name locks in reports. A lock held by a `LockedFile` is
+//! forgotten when the guard is released on the thread that took it. A lock held by
+//! a bare `File` is forgotten once its file descriptor refers to another file or,
+//! on Linux, no longer holds a lock according to `/proc/self/fdinfo`, which tells
+//! a closed descriptor apart from one reused for the same file.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+type Key = (u64, u64);
+
+thread_local! {
+    static HELD: RefCell<Vec<(Key, RawFd)>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Default)]
+struct Graph {
+    edges: HashMap<Key, HashSet<Key>>,
+    names: HashMap<Key, PathBuf>,
+    born: HashMap<Key, SystemTime>,
+}
+
+impl Graph {
+    /// Returns the chain of locks from `from` to `to`, if one was ever acquired in that order.
+    fn path(&self, from: Key, to: Key) -> Option<Vec<Key>> {
+        let mut stack = vec![vec![from]];
+        let mut visited = HashSet::new();
+
+        while let Some(chain) = stack.pop() {
+            let last = *chain.last().unwrap();
+            if last == to {
+                return Some(chain);
+            }
+            if !visited.insert(last) {
+                continue;
+            }
+
+            for next in self.edges.get(&last).into_iter().flatten() {
+                let mut chain = chain.clone();
+                chain.push(*next);
+                stack.push(chain);
+            }
+        }
+
+        None
+    }
+
+    /// Records the path and birth time of `key`. A different birth time than before
+    /// means that the inode was freed and reused for another file, whose order is
+    /// unrelated.
+    fn record(&mut self, key: Key, path: &Path, born: Option<SystemTime>) {
+        if self.names.get(&key).is_none_or(|name| name != path) {
+            self.names.insert(key, path.to_path_buf());
+        }
+
+        let born = match born {
+            Some(born) => born,
+            None => return,
+        };
+        if self.born.insert(key, born).is_some_and(|previous| previous != born) {
+            self.edges.remove(&key);
+            for targets in self.edges.values_mut() {
+                targets.remove(&key);
+            }
+        }
+    }
+
+    fn name(&self, key: Key) -> String {
+        match self.names.get(&key) {
+            Some(path) => path.display().to_string(),
+            None => format!("{}:{}", key.0, key.1),
+        }
+    }
+}
+
+static GRAPH: Mutex<Option<Graph>> = Mutex::new(None);
+
+pub(crate) fn acquired<F: AsRawFd>(file: &F, path: &Path, blocking: bool) {
+    let fd = file.as_raw_fd();
+    let key = match fstat_key(fd) {
+        Some(key) => key,
+        None => return,
+    };
+
+    let held = HELD.with(|held| {
+        let mut held = held.borrow_mut();
+        held.retain(|&(key, fd)| still_held(key, fd));
+        let keys = held.iter().map(|&(key, _)| key).collect::<Vec<_>>();
+        held.push((key, fd));
+        keys
+    });
+
+    let report = {
+        let mut graph = GRAPH.lock().unwrap_or_else(|e| e.into_inner());
+        let graph = graph.get_or_insert_with(Graph::default);
+        // Also for non-blocking locks, so that a reused inode does not inherit the
+        // edges of a file that was only ever locked while holding others.
+        graph.record(key, path, birth_time(fd));
+
+        if !blocking {
+            return;
+        }
+
+        let mut report = None;
+        for prev in held.into_iter().filter(|&prev| prev != key) {
+            if report.is_none() {
+                if let Some(chain) = graph.path(key, prev) {
+                    let chain = chain.iter().map(|&k| graph.name(k)).collect::<Vec<_>>();
+                    report = Some(format!(
+                        "lock order inversion: locking {} while holding {}, but they were previously locked in the order {}",
+                        graph.name(key), graph.name(prev), chain.join(" -> ")));
+                }
+            }
+            graph.edges.entry(prev).or_default().insert(key);
+        }
+
+        report
+    };
+
+    if let Some(report) = report {
+        match std::env::var("LOCKEDFILE_LOCKDEP").as_deref() {
+            Ok("log") => eprintln!("lockedfile: {}", report),
+            _ => panic!("{}", report),
+        }
+    }
+}
+
+/// Forgets the lock held through `file`, whose guard is being released.
+pub(crate) fn released<F: AsRawFd>(file: &F) {
+    let fd = file.as_raw_fd();
+    HELD.with(|held| held.borrow_mut().retain(|&(_, held_fd)| held_fd != fd));
+}
+
+fn still_held(key: Key, fd: RawFd) -> bool {
+    fstat_key(fd) == Some(key) && holds_lock(fd)
+}
+
+/// Whether the open file description behind `fd` holds a lock; fdinfo lists them
+/// since Linux 4.1. Without /proc, `fd` is assumed to.
+#[cfg(target_os = "linux")]
+fn holds_lock(fd: RawFd) -> bool {
+    match std::fs::read_to_string(format!("/proc/self/fdinfo/{}", fd)) {
+        Ok(info) => info.lines().any(|line| line.starts_with("lock:")),
+        Err(_) => true,
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn holds_lock(_fd: RawFd) -> bool {
+    true
+}
+
+fn birth_time(fd: RawFd) -> Option<SystemTime> {
+    use std::os::unix::io::FromRawFd;
+
+    // Borrows `fd` for std, which knows how each system reports birth times.
+    let file = std::mem::ManuallyDrop::new(unsafe { std::fs::File::from_raw_fd(fd) });
+
+    file.metadata().and_then(|meta| meta.created()).ok()
+}
+
+fn fstat_key(fd: RawFd) -> Option<Key> {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+        return None;
+    }
+
+    Some((stat.st_dev as u64, stat.st_ino as u64))
+}
@@ -0,0 +1,69 @@
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+use crate::LockMode;
+
+/// One file of a lock set, identified by device and inode so that every process sorts it the same way.
+pub(crate) struct Entry {
+    pub key: (u64, u64),
+    pub paths: Vec<PathBuf>,
+    pub mode: LockMode,
+}
+
+/// Resolves the requested paths into entries in acquisition order.
+///
+/// Paths naming the same file are merged into one entry, locked exclusively
+/// if any of them asked for it.
+pub(crate) fn plan(requests: &[(PathBuf, LockMode)], create: bool) -> std::io::Result<Vec<Entry>> {
+    let mut entries: Vec<Entry> = Vec::with_capacity(requests.len());
+
+    for (path, mode) in requests {
+        if create {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?;
+        }
+
+        let meta = std::fs::metadata(path)?;
+        let key = (meta.dev(), meta.ino());
+
+        match entries.iter_mut().find(|entry| entry.key == key) {
+            Some(entry) => {
+                entry.paths.push(path.clone());
+                if *mode == LockMode::Exclusive {
+                    entry.mode = LockMode::Exclusive;
+                }
+            },
+            None => entries.push(Entry {
+                key,
+                paths: vec![path.clone()],
+                mode: *mode,
+            }),
+        }
+    }
+
+    entries.sort_by_key(|entry| entry.key);
+
+    Ok(entries)
+}
+
+/// Whether a file locked for `key`, whose metadata is `meta`, is still the file that
+/// every path of the entry names. If a path was replaced after planning, the set has
+/// to be planned again to keep locking in the same order as other processes.
+pub(crate) fn is_current(key: (u64, u64), paths: &[PathBuf], meta: &std::fs::Metadata) -> std::io::Result<bool> {
+    if (meta.dev(), meta.ino()) != key {
+        return Ok(false);
+    }
+
+    for path in paths {
+        match std::fs::metadata(path) {
+            Ok(current) if (current.dev(), current.ino()) == key => {},
+            Ok(_) => return Ok(false),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(true)
+}
@@ -36,5 +36,11 @@ macro_rules! impl_open_options {
 
             self
         }
+
+        pub fn on_unreliable_lock(&mut self, policy: $crate::UnreliableLock) -> &mut Self {
+            self.unreliable = policy;
+
+            self
+        }
     };
 }
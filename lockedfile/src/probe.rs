@@ -0,0 +1,342 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::sys::fcntl_lock;
+
+/// How well a locking mechanism works on a filesystem.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Support {
+    /// Locks conflict with every other process using the filesystem.
+    Full,
+    /// Locks are enforced, but only among processes on this host or this mount.
+    Local,
+    /// Locks fail or are silently ignored.
+    Unsupported,
+}
+
+/// The result of `probe`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Capabilities {
+    pub filesystem: String,
+    pub flock: Support,
+    pub posix: Support,
+    pub ofd: Support,
+}
+
+impl Capabilities {
+    /// Whether the locks taken by `OpenOptions` are meaningful on this filesystem.
+    pub fn is_reliable(&self) -> bool {
+        self.flock == Support::Full
+    }
+}
+
+/// What `OpenOptions` does when asked to lock a file on a filesystem where the lock is not reliable.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum UnreliableLock {
+    /// Lock without probing the filesystem.
+    #[default]
+    Ignore,
+    /// Lock anyway, but print a warning to stderr.
+    Warn,
+    /// Fail with `ErrorKind::Unsupported`.
+    Refuse,
+    /// Use open file description (OFD) locks when they are reliable, otherwise refuse.
+    ///
+    /// Like `flock` locks, OFD locks belong to the open file, so they conflict between
+    /// threads and between files opened separately in one process, and are released
+    /// only when that file is closed. Classic POSIX record locks are never used, since
+    /// they belong to the whole process. OFD locks only exist on Linux; elsewhere this
+    /// refuses like `Refuse`.
+    ///
+    /// OFD locks do not conflict with `flock` locks, so every process locking the file
+    /// must use this policy. They require the file to be opened for writing to lock it
+    /// exclusively and for reading to lock it shared.
+    Fallback,
+}
+
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub(crate) enum Backend {
+    Flock,
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    Ofd,
+}
+
+/// Probes which locking mechanisms work on the filesystem containing `path`.
+///
+/// `path` may be a directory or a file; for a file, the probe runs in its parent directory.
+/// A scratch file is created there and removed afterwards. Where none can be created,
+/// e.g. in a read-only directory, the locks are assumed to work and only the kind of
+/// filesystem is taken into account.
+pub fn probe<P: AsRef<Path>>(path: P) -> std::io::Result<Capabilities> {
+    let dir = probe_dir(path.as_ref());
+    let filesystem = filesystem_name(&dir)?;
+    let local = local_only(&dir, &filesystem);
+
+    let scratch = dir.join(format!(".lockedfile-probe-{}-{}",
+        std::process::id(),
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos()));
+    let (flock, posix, ofd) = match test_locks(&scratch) {
+        Some(result) => {
+            let _ = std::fs::remove_file(&scratch);
+            result
+        },
+        None => (true, true, cfg!(any(target_os = "linux", target_os = "android"))),
+    };
+
+    let classify = |works: bool, is_local: bool| match (works, is_local) {
+        (false, _) => Support::Unsupported,
+        (true, true) => Support::Local,
+        (true, false) => Support::Full,
+    };
+
+    Ok(Capabilities {
+        filesystem,
+        flock: classify(flock, local.flock),
+        posix: classify(posix, local.posix),
+        ofd: classify(ofd, local.posix),
+    })
+}
+
+pub(crate) fn select_backend(path: &Path, policy: UnreliableLock) -> std::io::Result<Backend> {
+    if policy == UnreliableLock::Ignore {
+        return Ok(Backend::Flock);
+    }
+
+    let (dev, caps) = cached_probe(path)?;
+    if caps.is_reliable() {
+        return Ok(Backend::Flock);
+    }
+
+    match policy {
+        UnreliableLock::Ignore => Ok(Backend::Flock),
+        UnreliableLock::Warn => {
+            if first_warning(dev) {
+                eprintln!("lockedfile: warning: flock is not reliable on {} ({}): {:?}",
+                    path.display(), caps.filesystem, caps.flock);
+            }
+            Ok(Backend::Flock)
+        },
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        UnreliableLock::Fallback if caps.ofd == Support::Full => Ok(Backend::Ofd),
+        UnreliableLock::Refuse | UnreliableLock::Fallback => {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                format!("file locks are not reliable on {} ({})", path.display(), caps.filesystem)))
+        },
+    }
+}
+
+/// Probes the filesystem of `path` once per device, which is returned along with the result.
+fn cached_probe(path: &Path) -> std::io::Result<(u64, Capabilities)> {
+    static CACHE: Mutex<Option<HashMap<u64, Capabilities>>> = Mutex::new(None);
+
+    let dir = probe_dir(path);
+    let dev = std::fs::metadata(&dir)?.dev();
+
+    if let Some(caps) = CACHE.lock().unwrap().get_or_insert_with(HashMap::new).get(&dev) {
+        return Ok((dev, caps.clone()));
+    }
+
+    let caps = probe(&dir)?;
+    CACHE.lock().unwrap()
+        .get_or_insert_with(HashMap::new)
+        .insert(dev, caps.clone());
+
+    Ok((dev, caps))
+}
+
+/// Whether no warning was printed yet for the filesystem on `dev`.
+fn first_warning(dev: u64) -> bool {
+    static WARNED: Mutex<Option<HashSet<u64>>> = Mutex::new(None);
+
+    WARNED.lock().unwrap().get_or_insert_with(HashSet::new).insert(dev)
+}
+
+fn probe_dir(path: &Path) -> PathBuf {
+    if path.is_dir() {
+        return path.to_path_buf();
+    }
+
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Returns `None` if the scratch file cannot be created.
+fn test_locks(scratch: &Path) -> Option<(bool, bool, bool)> {
+    let open = || std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(scratch)
+        .ok();
+    let first = open()?;
+    let second = open()?;
+
+    // Two descriptors of one process must conflict; otherwise the lock is not enforced.
+    let flock = unsafe { libc::flock(first.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0
+        && unsafe { libc::flock(second.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0;
+    unsafe { libc::flock(first.as_raw_fd(), libc::LOCK_UN) };
+
+    // POSIX locks never conflict within one process, so only check that they are accepted.
+    let posix = fcntl_lock(first.as_raw_fd(), libc::F_SETLK, libc::F_WRLCK, 0, 0).is_ok();
+    let _ = fcntl_lock(first.as_raw_fd(), libc::F_SETLK, libc::F_UNLCK, 0, 0);
+
+    Some((flock, posix, test_ofd(&first, &second)))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn test_ofd(first: &File, second: &File) -> bool {
+    fcntl_lock(first.as_raw_fd(), libc::F_OFD_SETLK, libc::F_WRLCK, 0, 0).is_ok()
+        && fcntl_lock(second.as_raw_fd(), libc::F_OFD_SETLK, libc::F_WRLCK, 0, 0).is_err()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn test_ofd(_first: &File, _second: &File) -> bool {
+    false
+}
+
+struct LocalOnly {
+    flock: bool,
+    posix: bool,
+}
+
+fn local_only(dir: &Path, filesystem: &str) -> LocalOnly {
+    match filesystem {
+        "nfs" => {
+            let local_lock = nfs_local_lock(dir);
+            LocalOnly {
+                flock: matches!(local_lock.as_deref(), Some("flock") | Some("all")),
+                posix: matches!(local_lock.as_deref(), Some("posix") | Some("all")),
+            }
+        },
+        // FUSE and 9p servers rarely forward locks; overlayfs locks do not follow copy-up.
+        "fuse" | "osxfuse" | "macfuse" | "fusefs" | "9p" | "overlay" => LocalOnly {
+            flock: true,
+            posix: true,
+        },
+        _ => LocalOnly {
+            flock: false,
+            posix: false,
+        },
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn nfs_local_lock(dir: &Path) -> Option<String> {
+    let dir = dir.canonicalize().ok()?;
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+
+    // Fields: id parent major:minor root mount-point options [optional...] - fstype source super-options
+    mountinfo.lines()
+        .filter_map(|line| {
+            let mut halves = line.splitn(2, " - ");
+            let mount_point = halves.next()?.split(' ').nth(4)?;
+            let super_options = halves.next()?.split(' ').nth(2)?;
+            Some((unescape(mount_point), super_options))
+        })
+        .filter(|(mount_point, _)| dir.starts_with(mount_point))
+        .max_by_key(|(mount_point, _)| mount_point.as_os_str().len())
+        .and_then(|(_, options)| {
+            options.split(',')
+                .find_map(|opt| opt.strip_prefix("local_lock="))
+                .map(|value| value.to_owned())
+        })
+}
+
+/// Decodes the octal escapes, like `\040` for a space, that mountinfo uses for
+/// whitespace and backslashes in paths.
+#[cfg(target_os = "linux")]
+fn unescape(field: &str) -> PathBuf {
+    use std::os::unix::ffi::OsStringExt;
+
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let code = tail.get(..3)
+            .filter(|_| byte == b'\\')
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match code {
+            Some(code) => {
+                bytes.push(code);
+                rest = &tail[3..];
+            },
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            },
+        }
+    }
+
+    PathBuf::from(std::ffi::OsString::from_vec(bytes))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn nfs_local_lock(_dir: &Path) -> Option<String> {
+    None
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "dragonfly"))]
+fn statfs(dir: &Path) -> std::io::Result<libc::statfs> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes())
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut buf: libc::statfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statfs(path.as_ptr(), &mut buf) } == 0 {
+        Ok(buf)
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn filesystem_name(dir: &Path) -> std::io::Result<String> {
+    let magic = statfs(dir)?.f_type as u64 & 0xffff_ffff;
+
+    let name = match magic {
+        0xef53 => "ext4",
+        0x5846_5342 => "xfs",
+        0x9123_683e => "btrfs",
+        0x2fc1_2fc1 => "zfs",
+        0xf2f5_2010 => "f2fs",
+        0x0102_1994 => "tmpfs",
+        0x8584_58f6 => "ramfs",
+        0x6969 => "nfs",
+        0x6573_5546 => "fuse",
+        0x0102_1997 => "9p",
+        0x794c_7630 => "overlay",
+        0xff53_4d42 => "cifs",
+        0xfe53_4d42 => "smb2",
+        0x517b => "smb",
+        0x00c3_6400 => "ceph",
+        0x5346_414f => "afs",
+        0x0116_1970 => "gfs2",
+        0x7461_636f => "ocfs2",
+        0x0bd0_0bd0 => "lustre",
+        _ => return Ok(format!("unknown({:#x})", magic)),
+    };
+
+    Ok(name.to_owned())
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "dragonfly"))]
+fn filesystem_name(dir: &Path) -> std::io::Result<String> {
+    let buf = statfs(dir)?;
+    let name = unsafe { std::ffi::CStr::from_ptr(buf.f_fstypename.as_ptr()) };
+
+    Ok(name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "macos", target_os = "ios", target_os = "freebsd", target_os = "openbsd", target_os = "dragonfly")))]
+fn filesystem_name(_dir: &Path) -> std::io::Result<String> {
+    Ok("unknown".to_owned())
+}
@@ -0,0 +1,189 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::std::OpenOptions;
+use crate::std::atomic::sync_parent;
+use crate::sys::{self, Backoff};
+
+const SUFFIX: &str = ".msg";
+const TEMP_PREFIX: &str = ".tmp-";
+/// How old a temporary file must be before `claim` removes it, if no producer holds it.
+const STALE_TEMP_AGE: Duration = Duration::from_secs(60);
+
+/// A multi-producer multi-consumer queue of messages in a spool directory.
+///
+/// Producers write a message to a hidden temporary file and rename it to
+/// `<nanos>-<id>.msg`, so consumers never see partial messages and roughly get
+/// them in the order they were published. A consumer claims a message by taking a
+/// non-blocking exclusive lock on it, and deletes it once processed. If the consumer
+/// dies first, the kernel drops the lock and the message can be claimed again.
+///
+/// Producers hold a lock on their temporary file until it is renamed, so `claim`
+/// removes temporary files left behind by producers that crashed, once they are
+/// older than a minute and nobody holds them.
+pub struct Queue {
+    dir: PathBuf,
+}
+
+impl Queue {
+    pub fn open<P: AsRef<Path>>(dir: P) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the path of the published message.
+    pub fn publish<C: AsRef<[u8]>>(&self, content: C) -> std::io::Result<PathBuf> {
+        let id = sys::unique_id();
+        let temp_path = self.dir.join(format!("{}{}", TEMP_PREFIX, id));
+        // The file is new, so the lock never blocks.
+        let mut temp = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .try_open_exclusive(&temp_path)?
+            .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::WouldBlock))?;
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let path = self.dir.join(format!("{:020}-{}{}", nanos, id, SUFFIX));
+
+        let result = temp.write_all(content.as_ref())
+            .and_then(|()| temp.sync_all())
+            .and_then(|()| std::fs::rename(&temp_path, &path));
+        if result.is_err() {
+            let _ = std::fs::remove_file(&temp_path);
+        }
+        result?;
+
+        sync_parent(&path)?;
+
+        Ok(path)
+    }
+
+    /// Claims the oldest message that nobody else has claimed, if any.
+    pub fn claim(&self) -> std::io::Result<Option<Claim>> {
+        self.remove_stale_temps()?;
+
+        for path in self.messages()? {
+            let file = match OpenOptions::new().read(true).try_open_exclusive(&path) {
+                Ok(Some(file)) => file,
+                Ok(None) => continue,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+
+            // The previous holder may have deleted the message before releasing it.
+            let meta = file.metadata()?;
+            match std::fs::metadata(&path) {
+                Ok(current) if (current.dev(), current.ino()) == (meta.dev(), meta.ino()) => {},
+                Ok(_) => continue,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+
+            return Ok(Some(Claim { path, file }));
+        }
+
+        Ok(None)
+    }
+
+    /// Waits up to `timeout` for a message to claim.
+    pub fn claim_timeout(&self, timeout: Duration) -> std::io::Result<Option<Claim>> {
+        let mut backoff = Backoff::new(timeout);
+
+        loop {
+            if let Some(claim) = self.claim()? {
+                return Ok(Some(claim));
+            }
+
+            match backoff.next_delay() {
+                Some(delay) => std::thread::sleep(delay),
+                None => return Ok(None),
+            }
+        }
+    }
+
+    /// The number of published messages, claimed or not.
+    pub fn len(&self) -> std::io::Result<usize> {
+        Ok(self.messages()?.len())
+    }
+
+    pub fn is_empty(&self) -> std::io::Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Removes the temporary files of producers that crashed before publishing.
+    fn remove_stale_temps(&self) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if !entry.file_name().to_string_lossy().starts_with(TEMP_PREFIX) {
+                continue;
+            }
+
+            let stale = entry.metadata()
+                .and_then(|meta| meta.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > STALE_TEMP_AGE);
+            if !stale {
+                continue;
+            }
+
+            // A producer that is still alive holds the lock until it has renamed the file.
+            if let Ok(Some(_lock)) = OpenOptions::new().read(true).try_open_exclusive(entry.path()) {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn messages(&self) -> std::io::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !name.starts_with('.') && name.ends_with(SUFFIX) {
+                paths.push(entry.path());
+            }
+        }
+        paths.sort();
+
+        Ok(paths)
+    }
+}
+
+/// A message claimed by this consumer. Dropping it without `complete` gives it back to the queue.
+pub struct Claim {
+    path: PathBuf,
+    file: std::fs::File,
+}
+
+impl Claim {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn content(&mut self) -> std::io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut buf)?;
+
+        Ok(buf)
+    }
+
+    /// Deletes the message, which must happen before the claim is released.
+    pub fn complete(self) -> std::io::Result<()> {
+        std::fs::remove_file(&self.path)
+    }
+}
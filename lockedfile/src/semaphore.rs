@@ -0,0 +1,93 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::std::OpenOptions;
+use crate::sys::{self, Backoff};
+
+/// A counting semaphore shared between processes.
+///
+/// Each of the `permits` permits is a lock file `slot-<i>` in `dir`, and holding a
+/// permit means holding an exclusive lock on one of them. A process that dies
+/// releases its permits along with its locks. All users of a directory must agree
+/// on the number of permits.
+pub struct Semaphore {
+    dir: PathBuf,
+    permits: usize,
+}
+
+impl Semaphore {
+    pub fn open<P: AsRef<Path>>(dir: P, permits: usize) -> std::io::Result<Self> {
+        if permits == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "a semaphore needs at least one permit"));
+        }
+        std::fs::create_dir_all(dir.as_ref())?;
+
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            permits,
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn permits(&self) -> usize {
+        self.permits
+    }
+
+    /// Waits until a permit is free.
+    pub fn acquire(&self) -> std::io::Result<Permit> {
+        self.acquire_with(Backoff::forever())
+    }
+
+    /// Fails with `ErrorKind::TimedOut` if no permit becomes free within `timeout`.
+    pub fn acquire_timeout(&self, timeout: Duration) -> std::io::Result<Permit> {
+        self.acquire_with(Backoff::new(timeout))
+    }
+
+    /// Returns `Ok(None)` if all permits are taken.
+    pub fn try_acquire(&self) -> std::io::Result<Option<Permit>> {
+        for slot in 0..self.permits {
+            let file = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .try_open_exclusive(self.slot_path(slot))?;
+
+            if let Some(file) = file {
+                return Ok(Some(Permit { slot, _file: file }));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn acquire_with(&self, mut backoff: Backoff) -> std::io::Result<Permit> {
+        loop {
+            if let Some(permit) = self.try_acquire()? {
+                return Ok(permit);
+            }
+
+            match backoff.next_delay() {
+                Some(delay) => std::thread::sleep(delay),
+                None => return Err(sys::timed_out(&self.dir)),
+            }
+        }
+    }
+
+    fn slot_path(&self, slot: usize) -> PathBuf {
+        self.dir.join(format!("slot-{}", slot))
+    }
+}
+
+/// A permit of a `Semaphore`, released when dropped.
+pub struct Permit {
+    slot: usize,
+    _file: std::fs::File,
+}
+
+impl Permit {
+    /// Which slot file the permit holds.
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+}
@@ -1,7 +1,51 @@
+use std::io::{Read, Write};
 use std::path::Path;
+use std::time::Duration;
+use crate::{LockMode, SyncPolicy};
+use crate::probe::Backend;
+use crate::sys;
 #[cfg(unix)]
 use std::os::unix::fs::OpenOptionsExt as UnixOpenOptionsExt;
 
+mod append_log;
+pub(crate) mod atomic;
+mod cache;
+mod election;
+mod guard;
+mod journal;
+mod kv;
+mod lease;
+mod lockset;
+#[cfg(feature = "memmap")]
+mod mmap;
+mod poison;
+#[cfg(feature = "memmap")]
+mod ring;
+mod rotate;
+mod tree;
+mod versioned;
+pub use append_log::{AppendLog, Records};
+pub use atomic::{atomic_write, AtomicWriter};
+pub use cache::{CacheDir, CacheEntry};
+pub use election::{Campaign, LeaderElection, Leadership};
+pub use guard::LockedFile;
+pub use journal::{recover, JournaledFile};
+pub use kv::KvStore;
+pub use lease::Lease;
+pub use lockset::{LockSet, LockSetGuard};
+#[cfg(feature = "memmap")]
+pub use mmap::{LockedMmap, LockedMmapMut};
+pub use poison::Poisoned;
+#[cfg(feature = "memmap")]
+pub use ring::{RingBuffer, RingReader};
+pub use rotate::{Rotation, RotatingLog};
+pub use tree::{LockTree, TreeGuard};
+pub use versioned::{Snapshot, VersionedFile};
+pub use crate::election::ElectionEvent;
+pub use crate::lease::LeaseRecord;
+pub(crate) use atomic::sync_parent;
+pub(crate) use guard::sync;
+
 pub struct SharedFile;
 
 impl SharedFile {
@@ -10,6 +54,21 @@ impl SharedFile {
             .read(true)
             .open_shared(path)
     }
+
+    pub fn lock<P: AsRef<Path>>(path: P) -> std::io::Result<LockedFile> {
+        OpenOptions::new()
+            .read(true)
+            .lock(path, LockMode::Shared)
+    }
+
+    /// Reads content written by `OwnedFile::write_framed`, failing with an error that
+    /// wraps `Corrupted` if its length or checksum does not match.
+    pub fn read_framed<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        Self::open(path)?.read_to_end(&mut buf)?;
+
+        crate::frame::decode(buf)
+    }
 }
 
 pub struct OwnedFile;
@@ -27,12 +86,32 @@ impl OwnedFile {
             .create(true)
             .open_exclusive(path)
     }
+
+    /// Opens the file for reading and writing, as needed for `LockedFile::map_mut`.
+    pub fn lock<P: AsRef<Path>>(path: P) -> std::io::Result<LockedFile> {
+        OpenOptions::new()
+            .read(true)
+            .write(true)
+            .lock(path, LockMode::Exclusive)
+    }
+
+    /// Replaces the content of the file with `content`, framed with its length and CRC32C.
+    pub fn write_framed<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, content: C) -> std::io::Result<()> {
+        let mut file = Self::create(path)?;
+        file.set_len(0)?;
+
+        file.write_all(&crate::frame::encode(content.as_ref()))
+    }
 }
 
 pub struct OpenOptions {
     sys: std::fs::OpenOptions,
     #[cfg(unix)]
     flags: i32,
+    unreliable: crate::UnreliableLock,
+    sync: SyncPolicy,
+    sync_parent: bool,
+    poison: bool,
 }
 
 impl OpenOptions {
@@ -41,21 +120,165 @@ impl OpenOptions {
             sys: std::fs::OpenOptions::new(),
             #[cfg(unix)]
             flags: 0,
+            unreliable: crate::UnreliableLock::default(),
+            sync: SyncPolicy::default(),
+            sync_parent: false,
+            poison: false,
         }
     }
 
     crate::impl_open_options!();
 
+    /// Flushes exclusively locked files to disk before their lock is released.
+    ///
+    /// This only applies to `LockedFile` guards returned by `lock` and `try_lock`;
+    /// a bare `File` releases its lock when closed, without a chance to sync.
+    pub fn sync_on_unlock(&mut self, policy: SyncPolicy) -> &mut Self {
+        self.sync = policy;
+
+        self
+    }
+
+    /// Also syncs the parent directory, so that a newly created file survives a crash.
+    pub fn sync_parent_on_unlock(&mut self, sync_parent: bool) -> &mut Self {
+        self.sync_parent = sync_parent;
+
+        self
+    }
+
+    /// Detects exclusive holders that went away without releasing their guard cleanly.
+    ///
+    /// While an exclusive guard returned by `lock` or `try_lock` is held, the sidecar
+    /// file `<name>.poison` exists. It is removed when the guard is dropped or
+    /// `unlock`ed, but not when the process dies or the thread panics. As long as it
+    /// exists, `lock` and `try_lock` fail with an error wrapping `Poisoned`, which
+    /// still grants access to the locked file for recovery. The marker stays until an
+    /// exclusive holder calls `LockedFile::clear_poison` and releases the file cleanly.
+    pub fn poison_on_crash(&mut self, poison: bool) -> &mut Self {
+        self.poison = poison;
+
+        self
+    }
+
+    pub fn lock<P: AsRef<Path>>(&self, path: P, mode: LockMode) -> std::io::Result<LockedFile> {
+        let file = self.open_with(path.as_ref(), mode)?;
+
+        self.guard(file, path.as_ref(), mode)
+    }
+
+    pub fn try_lock<P: AsRef<Path>>(&self, path: P, mode: LockMode) -> std::io::Result<Option<LockedFile>> {
+        match self.try_open_with(path.as_ref(), mode)? {
+            Some(file) => self.guard(file, path.as_ref(), mode).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn guard(&self, file: std::fs::File, path: &Path, mode: LockMode) -> std::io::Result<LockedFile> {
+        let mut guard = LockedFile::new(file, path.to_path_buf(), mode, self.sync, self.sync_parent);
+        if !self.poison {
+            return Ok(guard);
+        }
+
+        let (poisoned, marker) = poison::enter(path, mode == LockMode::Exclusive)?;
+        guard.set_poison_marker(marker, poisoned);
+
+        if poisoned {
+            Err(std::io::Error::other(Poisoned::new(guard)))
+        } else {
+            Ok(guard)
+        }
+    }
+
     pub fn open_exclusive<P: AsRef<Path>>(&self, path: P) -> std::io::Result<std::fs::File> {
-        self.sys.clone()
-            .custom_flags(self.flags | libc::O_EXLOCK)
-            .open(path)
+        self.open_with(path, LockMode::Exclusive)
     }
 
     pub fn open_shared<P: AsRef<Path>>(&self, path: P) -> std::io::Result<std::fs::File> {
-        self.sys.clone()
-            .custom_flags(self.flags | libc::O_SHLOCK)
-            .open(path)
+        self.open_with(path, LockMode::Shared)
+    }
+
+    pub fn open_with<P: AsRef<Path>>(&self, path: P, mode: LockMode) -> std::io::Result<std::fs::File> {
+        self.open_locked(path.as_ref(), mode, true)?
+            .ok_or_else(|| std::io::ErrorKind::WouldBlock.into())
+    }
+
+    /// Returns `Ok(None)` instead of blocking if the file is locked by someone else.
+    pub fn try_open_exclusive<P: AsRef<Path>>(&self, path: P) -> std::io::Result<Option<std::fs::File>> {
+        self.try_open_with(path, LockMode::Exclusive)
+    }
+
+    /// Returns `Ok(None)` instead of blocking if the file is locked exclusively by someone else.
+    pub fn try_open_shared<P: AsRef<Path>>(&self, path: P) -> std::io::Result<Option<std::fs::File>> {
+        self.try_open_with(path, LockMode::Shared)
+    }
+
+    pub fn try_open_with<P: AsRef<Path>>(&self, path: P, mode: LockMode) -> std::io::Result<Option<std::fs::File>> {
+        self.open_locked(path.as_ref(), mode, false)
+    }
+
+    /// Fails with `ErrorKind::TimedOut` if the lock cannot be taken within `timeout`.
+    pub fn open_timeout<P: AsRef<Path>>(&self, path: P, mode: LockMode, timeout: Duration) -> std::io::Result<std::fs::File> {
+        let path = path.as_ref();
+        let mut backoff = sys::Backoff::new(timeout);
+
+        loop {
+            if let Some(file) = self.open_locked(path, mode, false)? {
+                return Ok(file);
+            }
+
+            match backoff.next_delay() {
+                Some(delay) => std::thread::sleep(delay),
+                None => return Err(sys::timed_out(path)),
+            }
+        }
+    }
+
+    fn open_locked(&self, path: &Path, mode: LockMode, wait: bool) -> std::io::Result<Option<std::fs::File>> {
+        let file = match (crate::probe::select_backend(path, self.unreliable)?, mode.open_flag()) {
+            (Backend::Flock, Some(flag)) => {
+                let nonblock = if wait { 0 } else { libc::O_NONBLOCK };
+                let result = self.sys.clone()
+                    .custom_flags(self.flags | flag | nonblock)
+                    .open(path);
+
+                match result {
+                    Ok(file) => {
+                        if !wait && self.flags & libc::O_NONBLOCK == 0 {
+                            sys::clear_nonblock(&file)?;
+                        }
+                        file
+                    },
+                    Err(e) if !wait && sys::is_would_block(&e) => return Ok(None),
+                    Err(e) => return Err(e),
+                }
+            },
+            (Backend::Flock, None) => {
+                let file = self.sys.clone()
+                    .custom_flags(self.flags)
+                    .open(path)?;
+
+                if !sys::flock(&file, mode, wait)? {
+                    return Ok(None);
+                }
+                file
+            },
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            (Backend::Ofd, _) => {
+                let file = self.sys.clone()
+                    .custom_flags(self.flags)
+                    .open(path)?;
+
+                if !sys::ofd_lock(&file, mode, wait)? {
+                    return Ok(None);
+                }
+                file
+            },
+        };
+
+        #[cfg(feature = "lockdep")]
+        crate::lockdep::acquired(&file, path, wait);
+
+        Ok(Some(file))
     }
 }
 
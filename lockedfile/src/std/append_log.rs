@@ -0,0 +1,197 @@
+use std::convert::TryFrom;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::frame::{crc32c, Corrupted};
+use super::OpenOptions;
+
+const HEADER_LEN: usize = 8;
+
+/// A file of records that several processes append to.
+///
+/// Each record is prefixed with its length and CRC32C as little-endian u32s and
+/// written with a single `write` on an `O_APPEND` descriptor, under an exclusive lock
+/// that is only held for that write, so records never interleave. Readers stop at a
+/// tail record that was cut short, e.g. by a crash, or whose checksum does not match.
+/// Such a tail is truncated before anything is appended behind it: `open` checks the
+/// whole log, and `append` checks the records other writers added since this log
+/// last saw the end of the file. A corrupted record with more records behind it is
+/// not a torn tail, so the log is left as it is and `Corrupted` is returned instead.
+pub struct AppendLog {
+    path: PathBuf,
+    /// The end of the last record known to be intact.
+    end: AtomicU64,
+}
+
+impl AppendLog {
+    /// Creates the log if it does not exist; truncates an existing one at a last
+    /// record that was cut short or is corrupted.
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open_exclusive(&path)?;
+        let end = repair(&file, 0)?;
+
+        Ok(Self { path, end: AtomicU64::new(end) })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append<R: AsRef<[u8]>>(&self, record: R) -> std::io::Result<()> {
+        let record = record.as_ref();
+        let len = u32::try_from(record.len()).map_err(|_| {
+            std::io::Error::new(std::io::ErrorKind::InvalidInput, "record is larger than 4 GiB")
+        })?;
+
+        let mut buf = Vec::with_capacity(HEADER_LEN + record.len());
+        buf.extend_from_slice(&len.to_le_bytes());
+        buf.extend_from_slice(&crc32c(record).to_le_bytes());
+        buf.extend_from_slice(record);
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open_exclusive(&self.path)?;
+
+        // Another writer may have died halfway through a record since the last append.
+        // A log that shrank below the known end was repaired or replaced, so it is
+        // checked from the start.
+        let mut end = self.end.load(Ordering::SeqCst);
+        let len = file.metadata()?.len();
+        if len < end {
+            end = 0;
+        }
+        if len != end {
+            end = repair(&file, end)?;
+        }
+
+        file.write_all(&buf)?;
+        self.end.store(end + buf.len() as u64, Ordering::SeqCst);
+
+        Ok(())
+    }
+
+    /// Iterates over the records under a shared lock, which blocks appends until the iterator is dropped.
+    pub fn records(&self) -> std::io::Result<Records> {
+        let file = OpenOptions::new()
+            .read(true)
+            .open_shared(&self.path)?;
+
+        Ok(Records::new(file))
+    }
+
+    /// Iterates over the records without locking; a record being appended concurrently
+    /// is not returned, as it looks like a truncated tail.
+    pub fn records_unlocked(&self) -> std::io::Result<Records> {
+        Ok(Records::new(std::fs::File::open(&self.path)?))
+    }
+}
+
+/// An iterator over the records of an `AppendLog`.
+///
+/// It ends at a truncated tail record, and after returning an error wrapping
+/// `Corrupted` for a record whose checksum does not match.
+pub struct Records {
+    reader: BufReader<std::fs::File>,
+    offset: u64,
+    done: bool,
+}
+
+impl Records {
+    fn starting_at(mut file: std::fs::File, offset: u64) -> std::io::Result<Self> {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut records = Self::new(file);
+        records.offset = offset;
+
+        Ok(records)
+    }
+
+    fn new(file: std::fs::File) -> Self {
+        Self {
+            reader: BufReader::new(file),
+            offset: 0,
+            done: false,
+        }
+    }
+
+    fn read_record(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        let mut header = [0u8; HEADER_LEN];
+        if !read_full(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&header[..4]);
+        let mut checksum = [0u8; 4];
+        checksum.copy_from_slice(&header[4..]);
+
+        // Not preallocated, since the length of a truncated record may be garbage.
+        let len = u32::from_le_bytes(len) as usize;
+        let mut record = Vec::new();
+        if (&mut self.reader).take(len as u64).read_to_end(&mut record)? < len {
+            return Ok(None);
+        }
+        if crc32c(&record) != u32::from_le_bytes(checksum) {
+            return Err(Corrupted { reason: "checksum mismatch" }.into());
+        }
+
+        self.offset += (HEADER_LEN + record.len()) as u64;
+
+        Ok(Some(record))
+    }
+}
+
+impl Iterator for Records {
+    type Item = std::io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.read_record().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+
+        result
+    }
+}
+
+/// Truncates `file` at a last record from `offset` on that is cut short or corrupted,
+/// and returns the new end. `offset` must be the start of a record.
+fn repair(file: &std::fs::File, offset: u64) -> std::io::Result<u64> {
+    let mut records = Records::starting_at(file.try_clone()?, offset)?;
+    while let Some(record) = records.next() {
+        match record {
+            Ok(_) => {},
+            // Only a corrupted record that runs to the end of the file is a torn tail.
+            Err(e) if e.get_ref().is_some_and(|inner| inner.is::<Corrupted>()) => {
+                if read_full(&mut records.reader, &mut [0u8])? {
+                    return Err(e);
+                }
+                break;
+            },
+            Err(e) => return Err(e),
+        }
+    }
+    if records.offset < file.metadata()?.len() {
+        file.set_len(records.offset)?;
+    }
+
+    Ok(records.offset)
+}
+
+/// Fills `buf`, or returns false if the end of the file comes first.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,268 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::LockMode;
+use super::{LockedFile, OpenOptions};
+
+/// Replaces the content of `path` so that readers see either the old or the new content.
+pub fn atomic_write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> std::io::Result<()> {
+    let mut writer = AtomicWriter::new(path)?;
+    writer.write_all(contents.as_ref())?;
+
+    writer.commit()
+}
+
+/// Writes a new version of a file next to it and renames it over the original on `commit`.
+///
+/// Writers are serialized by an exclusive lock on the sidecar file `<name>.lock`
+/// rather than on the target itself, because the rename replaces the target's inode
+/// and a lock on the old inode would no longer exclude anyone. The new content is
+/// synced before the rename and the directory after it, so a crash leaves either the
+/// old or the new file in place. Dropping the writer without committing discards it.
+///
+/// The new version is written to `<name>.tmp`, which only the holder of the lock
+/// uses, so one left behind by a crash is removed by the next writer.
+pub struct AtomicWriter {
+    target: PathBuf,
+    temp_path: Option<PathBuf>,
+    temp: std::fs::File,
+    _lock: LockedFile,
+    committed: bool,
+}
+
+impl AtomicWriter {
+    pub fn new<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Self::create(path.as_ref(), false)
+    }
+
+    /// Writes into an anonymous `O_TMPFILE` inode that only gets a name on `commit`,
+    /// so a crash while writing leaves nothing behind.
+    ///
+    /// Falls back to a named temporary file where `O_TMPFILE` is unavailable
+    /// (on other systems than Linux, or filesystems without support for it).
+    /// Since `linkat` cannot replace an existing file, an existing target is still
+    /// replaced through a briefly named link and `rename`. The inode is linked with
+    /// `AT_EMPTY_PATH` where the process may, otherwise through `/proc/self/fd`, and
+    /// without `/proc` its content is copied to a named temporary file on `commit`.
+    pub fn anonymous<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        Self::create(path.as_ref(), true)
+    }
+
+    fn create(target: &Path, anonymous: bool) -> std::io::Result<Self> {
+        // The sidecar may be removed by its owner, e.g. `KvStore::delete`, so the lock
+        // must be taken on the file that is still linked.
+        let lock_path = lock_path(target);
+        let lock = loop {
+            let lock = OpenOptions::new()
+                .write(true)
+                .create(true)
+                .lock(&lock_path, LockMode::Exclusive)?;
+            if crate::sys::is_linked_at(&lock, &lock_path)? {
+                break lock;
+            }
+        };
+
+        // Left behind by a writer that crashed while holding the lock.
+        match std::fs::remove_file(temp_path(target)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {},
+        }
+
+        let (temp, temp_path) = match open_anonymous(target, anonymous)? {
+            Some(temp) => (temp, None),
+            None => {
+                let temp_path = temp_path(target);
+                let temp = std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&temp_path)?;
+                (temp, Some(temp_path))
+            },
+        };
+
+        let writer = Self {
+            target: target.to_path_buf(),
+            temp_path,
+            temp,
+            _lock: lock,
+            committed: false,
+        };
+
+        if let Ok(meta) = std::fs::metadata(target) {
+            writer.temp.set_permissions(meta.permissions())?;
+        }
+
+        Ok(writer)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.target
+    }
+
+    pub fn commit(mut self) -> std::io::Result<()> {
+        self.temp.flush()?;
+        self.temp.sync_all()?;
+
+        match &self.temp_path {
+            Some(temp_path) => std::fs::rename(temp_path, &self.target)?,
+            None => link_anonymous(&self.temp, &self.target)?,
+        }
+        self.committed = true;
+
+        sync_parent(&self.target)
+    }
+}
+
+impl Write for AtomicWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.temp.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.temp.flush()
+    }
+}
+
+impl Drop for AtomicWriter {
+    fn drop(&mut self) {
+        if let (Some(temp_path), false) = (&self.temp_path, self.committed) {
+            let _ = std::fs::remove_file(temp_path);
+        }
+    }
+}
+
+/// The sidecar file that serializes writers replacing `target`.
+pub(crate) fn lock_path(target: &Path) -> PathBuf {
+    sibling(target, ".lock")
+}
+
+/// `target` with `suffix` appended to its file name.
+pub(crate) fn sibling(target: &Path, suffix: &str) -> PathBuf {
+    let mut name = target.file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    name.push(suffix);
+
+    target.with_file_name(name)
+}
+
+fn temp_path(target: &Path) -> PathBuf {
+    sibling(target, ".tmp")
+}
+
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+pub(crate) fn sync_parent(path: &Path) -> std::io::Result<()> {
+    std::fs::File::open(parent_dir(path))?.sync_all()
+}
+
+#[cfg(target_os = "linux")]
+fn open_anonymous(target: &Path, anonymous: bool) -> std::io::Result<Option<std::fs::File>> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    if !anonymous {
+        return Ok(None);
+    }
+
+    let result = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_TMPFILE)
+        .mode(0o666)
+        .open(parent_dir(target));
+
+    match result {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if matches!(e.raw_os_error(), Some(libc::EOPNOTSUPP) | Some(libc::EISDIR) | Some(libc::EINVAL)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_anonymous(_target: &Path, _anonymous: bool) -> std::io::Result<Option<std::fs::File>> {
+    Ok(None)
+}
+
+#[cfg(target_os = "linux")]
+fn link_anonymous(file: &std::fs::File, target: &Path) -> std::io::Result<()> {
+    let temp_path = temp_path(target);
+
+    match link(file, target) {
+        Ok(true) => return Ok(()),
+        Ok(false) => copy_to_new(file, &temp_path)?,
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+            link(file, &temp_path)?;
+        },
+        Err(e) => return Err(e),
+    }
+
+    std::fs::rename(&temp_path, target).inspect_err(|_| {
+        let _ = std::fs::remove_file(&temp_path);
+    })
+}
+
+/// Gives the anonymous `file` the name `dest`, or returns false if the system
+/// offers no way to do so.
+#[cfg(target_os = "linux")]
+fn link(file: &std::fs::File, dest: &Path) -> std::io::Result<bool> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    let dest = CString::new(dest.as_os_str().as_bytes())?;
+
+    // Needs CAP_DAC_READ_SEARCH on most kernels, and fails with ENOENT without it.
+    let ret = unsafe { libc::linkat(fd, b"\0".as_ptr() as *const libc::c_char, libc::AT_FDCWD, dest.as_ptr(), libc::AT_EMPTY_PATH) };
+    if ret == 0 {
+        return Ok(true);
+    }
+    let e = std::io::Error::last_os_error();
+    if !matches!(e.raw_os_error(), Some(libc::ENOENT) | Some(libc::EPERM)) {
+        return Err(e);
+    }
+
+    let proc_fd = format!("/proc/self/fd/{}", fd);
+    if !Path::new(&proc_fd).exists() {
+        return Ok(false);
+    }
+    let source = CString::new(proc_fd)?;
+    let ret = unsafe {
+        libc::linkat(libc::AT_FDCWD, source.as_ptr(), libc::AT_FDCWD, dest.as_ptr(), libc::AT_SYMLINK_FOLLOW)
+    };
+    if ret == 0 {
+        Ok(true)
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Copies the content and permissions of `file` to a new file at `dest` and syncs it.
+#[cfg(target_os = "linux")]
+fn copy_to_new(file: &std::fs::File, dest: &Path) -> std::io::Result<()> {
+    use std::io::{Seek, SeekFrom};
+
+    let mut source = file.try_clone()?;
+    source.seek(SeekFrom::Start(0))?;
+    let mut copy = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dest)?;
+
+    let result = std::io::copy(&mut source, &mut copy)
+        .and_then(|_| copy.set_permissions(file.metadata()?.permissions()))
+        .and_then(|()| copy.sync_all());
+
+    result.inspect_err(|_| {
+        let _ = std::fs::remove_file(dest);
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn link_anonymous(_file: &std::fs::File, _target: &Path) -> std::io::Result<()> {
+    unreachable!("anonymous temporary files are only created on Linux")
+}
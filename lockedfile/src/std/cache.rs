@@ -0,0 +1,287 @@
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::{Deref, DerefMut};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use super::{AtomicWriter, OpenOptions};
+use super::atomic::lock_path;
+use super::kv;
+use crate::{sys, LockMode};
+
+const INDEX: &str = ".index";
+
+/// A cache directory shared by concurrent processes, with least-recently-used eviction.
+///
+/// Each entry is a file that readers hold a shared lock on while they use it, and
+/// that `insert` fills under an exclusive lock. The index file `.index` records the
+/// size of every complete entry; files missing from it were not completely written
+/// and read as absent. The last use of an entry is its modification time, which
+/// `get` sets without touching the index. `gc` evicts the least recently used
+/// entries, skipping any entry it cannot lock exclusively right away because it
+/// is in use.
+///
+/// Keys are named as in `KvStore`: percent-encoded, and hashed if that is too long.
+pub struct CacheDir {
+    dir: PathBuf,
+}
+
+/// What `try_remove` did with an entry file.
+enum Removal {
+    /// The file was removed, and stays locked until the index is updated.
+    Removed(std::fs::File),
+    InUse,
+    Missing,
+}
+
+impl CacheDir {
+    pub fn open<P: AsRef<Path>>(dir: P) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the entry under a shared lock, which keeps it from being evicted or overwritten.
+    pub fn get(&self, key: &str) -> std::io::Result<Option<CacheEntry>> {
+        let name = entry_name(key)?;
+        let file = match self.lock_entry(&name, OpenOptions::new().read(true), LockMode::Shared) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let _lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open_shared(lock_path(&self.index_path()))?;
+        if !read_index(&self.index_path())?.contains_key(&name) {
+            return Ok(None);
+        }
+        // Readers that may not change the file's times just do not count as a use.
+        let _ = touch(&file);
+
+        Ok(Some(CacheEntry {
+            key: key.to_owned(),
+            file,
+        }))
+    }
+
+    /// Writes an entry, waiting for its readers to finish first.
+    pub fn insert<C: AsRef<[u8]>>(&self, key: &str, content: C) -> std::io::Result<()> {
+        let name = entry_name(key)?;
+        let mut options = OpenOptions::new();
+        options.write(true).create(true).truncate(false);
+        let mut file = self.lock_entry(&name, &options, LockMode::Exclusive)?;
+
+        self.update_index(|index| index.remove(&name))?;
+        file.set_len(0)?;
+        file.write_all(content.as_ref())?;
+        file.sync_data()?;
+
+        let size = content.as_ref().len() as u64;
+        self.update_index(|index| index.insert(name, size))?;
+
+        Ok(())
+    }
+
+    /// The total size of all complete entries.
+    pub fn size(&self) -> std::io::Result<u64> {
+        let _lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open_shared(lock_path(&self.index_path()))?;
+
+        Ok(read_index(&self.index_path())?.values().sum())
+    }
+
+    /// Evicts least recently used entries until the total size is at most `max_size`,
+    /// and removes files of incomplete entries. Returns the number of bytes freed.
+    ///
+    /// Entries in use are skipped, so the cache may stay larger than `max_size`.
+    /// Index records whose file is missing are dropped without counting as freed.
+    pub fn gc(&self, max_size: u64) -> std::io::Result<u64> {
+        let mut writer = AtomicWriter::new(self.index_path())?;
+        let mut index = read_index(&self.index_path())?;
+        let mut freed = 0;
+        let mut evicted = Vec::new();
+
+        let mut candidates = Vec::new();
+        for name in index.keys().cloned().collect::<Vec<_>>() {
+            match std::fs::metadata(self.dir.join(&name)).and_then(|meta| meta.modified()) {
+                Ok(last_used) => candidates.push((last_used, name)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    index.remove(&name);
+                },
+                Err(e) => return Err(e),
+            }
+        }
+        candidates.sort();
+
+        let mut size: u64 = index.values().sum();
+        for (_, name) in candidates {
+            if size <= max_size {
+                break;
+            }
+            match self.try_remove(&name)? {
+                Removal::Removed(file) => {
+                    let record = index.remove(&name).unwrap();
+                    size -= record;
+                    freed += record;
+                    evicted.push(file);
+                },
+                Removal::InUse => {},
+                Removal::Missing => {
+                    size -= index.remove(&name).unwrap();
+                },
+            }
+        }
+
+        for entry in std::fs::read_dir(&self.dir)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if kv::is_entry_name(&name) && !index.contains_key(&name) {
+                if let Removal::Removed(file) = self.try_remove(&name)? {
+                    evicted.push(file);
+                }
+            }
+        }
+
+        writer.write_all(&encode_index(&index))?;
+        writer.commit()?;
+
+        Ok(freed)
+    }
+
+    /// Locks the entry file, making sure that the lock was not taken on a file that
+    /// `gc` removed after it was opened.
+    fn lock_entry(&self, name: &str, options: &OpenOptions, mode: LockMode) -> std::io::Result<std::fs::File> {
+        let path = self.dir.join(name);
+
+        loop {
+            let file = options.open_with(&path, mode)?;
+            if sys::is_linked_at(&file, &path)? {
+                return Ok(file);
+            }
+        }
+    }
+
+    /// Removes an entry file unless it is locked.
+    fn try_remove(&self, name: &str) -> std::io::Result<Removal> {
+        let path = self.dir.join(name);
+        match OpenOptions::new().read(true).try_open_exclusive(&path) {
+            Ok(Some(file)) => {
+                std::fs::remove_file(&path)?;
+                Ok(Removal::Removed(file))
+            },
+            Ok(None) => Ok(Removal::InUse),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Removal::Missing),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn update_index<F, R>(&self, f: F) -> std::io::Result<R>
+    where
+        F: FnOnce(&mut BTreeMap<String, u64>) -> R,
+    {
+        let mut writer = AtomicWriter::new(self.index_path())?;
+        let mut index = read_index(&self.index_path())?;
+        let result = f(&mut index);
+        writer.write_all(&encode_index(&index))?;
+        writer.commit()?;
+
+        Ok(result)
+    }
+
+    fn index_path(&self) -> PathBuf {
+        self.dir.join(INDEX)
+    }
+}
+
+/// An entry of a `CacheDir`, locked shared until dropped.
+pub struct CacheEntry {
+    key: String,
+    file: std::fs::File,
+}
+
+impl CacheEntry {
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn read_all(&mut self) -> std::io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut buf)?;
+
+        Ok(buf)
+    }
+}
+
+impl Deref for CacheEntry {
+    type Target = std::fs::File;
+
+    fn deref(&self) -> &std::fs::File {
+        &self.file
+    }
+}
+
+impl DerefMut for CacheEntry {
+    fn deref_mut(&mut self) -> &mut std::fs::File {
+        &mut self.file
+    }
+}
+
+fn entry_name(key: &str) -> std::io::Result<String> {
+    if key.is_empty() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "empty key"));
+    }
+
+    Ok(sys::file_name(key))
+}
+
+/// Lines of `<name> <size>`.
+fn read_index(path: &Path) -> std::io::Result<BTreeMap<String, u64>> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+        Err(e) => return Err(e),
+    };
+
+    let mut index = BTreeMap::new();
+    for line in content.lines() {
+        let mut fields = line.split(' ');
+        let record = match (fields.next(), fields.next()) {
+            (Some(name), Some(size)) => size.parse().ok().map(|size| (name.to_owned(), size)),
+            _ => None,
+        };
+        let (name, size) = record.ok_or_else(|| std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("malformed cache index: {}", path.display())))?;
+        index.insert(name, size);
+    }
+
+    Ok(index)
+}
+
+fn encode_index(index: &BTreeMap<String, u64>) -> Vec<u8> {
+    let mut buf = String::new();
+    for (name, size) in index {
+        buf.push_str(&format!("{} {}\n", name, size));
+    }
+
+    buf.into_bytes()
+}
+
+/// Sets the modification time of an entry to now, which only needs write access
+/// to the file or owning it.
+fn touch(file: &std::fs::File) -> std::io::Result<()> {
+    if unsafe { libc::futimens(file.as_raw_fd(), std::ptr::null()) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
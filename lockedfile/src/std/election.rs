@@ -0,0 +1,217 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use crate::LockMode;
+use crate::election::{parse_identity, same_file, write_identity, ElectionEvent, WAIT_SLICE};
+use super::OpenOptions;
+
+/// Elects one leader among the processes that hold a well-known file.
+///
+/// The leader is whoever holds the exclusive lock on the file, and it writes its
+/// identity into it. The kernel releases the lock when the leader exits, so a
+/// follower can take over without any timeout. Followers either try to lead now and
+/// then, or start a `campaign` that does so in the background and reports events.
+pub struct LeaderElection {
+    path: PathBuf,
+    identity: String,
+}
+
+impl LeaderElection {
+    pub fn new<P: AsRef<Path>, S: Into<String>>(path: P, identity: S) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            identity: identity.into(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// The identity of the current leader, or `None` if nobody holds the election file.
+    pub fn leader<P: AsRef<Path>>(path: P) -> std::io::Result<Option<String>> {
+        match OpenOptions::new().read(true).try_open_shared(path.as_ref()) {
+            Ok(Some(_)) => return Ok(None),
+            Ok(None) => {},
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        // The leader holds the lock for good, so its identity is read without one.
+        match std::fs::read(path) {
+            Ok(buf) => Ok(parse_identity(&buf)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Waits until this candidate becomes the leader.
+    pub fn lead(&self) -> std::io::Result<Leadership> {
+        let file = self.options().open_exclusive(&self.path)?;
+
+        self.take_office(file)
+    }
+
+    /// Returns `Ok(None)` if another candidate is the leader.
+    pub fn try_lead(&self) -> std::io::Result<Option<Leadership>> {
+        match self.options().try_open_exclusive(&self.path)? {
+            Some(file) => self.take_office(file).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Waits for leadership on a background thread, which reports when it is gained
+    /// or lost.
+    ///
+    /// The thread waits on the lock for 100ms at a time, checking in between whether
+    /// the campaign was stopped, so it takes over soon after the leader exits.
+    /// While leading, it checks every `interval` that the election file was not
+    /// replaced.
+    pub fn campaign(&self, interval: Duration) -> Campaign {
+        let election = Self::new(&self.path, self.identity.clone());
+        let (events, receiver) = mpsc::channel();
+        let stop = Arc::new((Mutex::new(false), Condvar::new()));
+        let leading = Arc::new(Mutex::new(false));
+
+        let thread = {
+            let stop = stop.clone();
+            let leading = leading.clone();
+            std::thread::spawn(move || {
+                let (stopped, cond) = &*stop;
+                // Waits for `interval` and returns whether the campaign was stopped.
+                let pause = || {
+                    let stopped = stopped.lock().unwrap();
+                    let (stopped, _) = cond.wait_timeout_while(stopped, interval, |stopped| !*stopped).unwrap();
+                    *stopped
+                };
+
+                loop {
+                    let file = match election.options().open_timeout(&election.path, LockMode::Exclusive, WAIT_SLICE) {
+                        Err(e) if e.kind() == std::io::ErrorKind::TimedOut => None,
+                        result => Some(result),
+                    };
+                    if *stopped.lock().unwrap() {
+                        return;
+                    }
+
+                    let leadership = match file.map(|file| file.and_then(|file| election.take_office(file))) {
+                        None => continue,
+                        Some(Ok(leadership)) => leadership,
+                        Some(Err(_)) if pause() => return,
+                        Some(Err(_)) => continue,
+                    };
+                    *leading.lock().unwrap() = true;
+                    let _ = events.send(ElectionEvent::Gained);
+
+                    loop {
+                        if pause() {
+                            return;
+                        }
+                        if let Ok(false) = leadership.is_valid() {
+                            break;
+                        }
+                    }
+                    *leading.lock().unwrap() = false;
+                    let _ = events.send(ElectionEvent::Lost);
+                }
+            })
+        };
+
+        Campaign {
+            events: receiver,
+            leading,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    fn options(&self) -> OpenOptions {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true);
+
+        options
+    }
+
+    fn take_office(&self, file: std::fs::File) -> std::io::Result<Leadership> {
+        write_identity(&file, &self.identity)?;
+
+        Ok(Leadership {
+            path: self.path.clone(),
+            file,
+        })
+    }
+}
+
+/// Held by the leader; dropping it steps down.
+pub struct Leadership {
+    path: PathBuf,
+    file: std::fs::File,
+}
+
+impl Leadership {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns false if the election file was removed or replaced, so that another
+    /// candidate may have become the leader through the new file.
+    pub fn is_valid(&self) -> std::io::Result<bool> {
+        match std::fs::metadata(&self.path) {
+            Ok(meta) => Ok(same_file(&meta, &self.file.metadata()?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for Leadership {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// A background campaign started by `LeaderElection::campaign`; dropping it stops
+/// the campaign and steps down.
+pub struct Campaign {
+    events: Receiver<ElectionEvent>,
+    leading: Arc<Mutex<bool>>,
+    stop: Arc<(Mutex<bool>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Campaign {
+    pub fn is_leader(&self) -> bool {
+        *self.leading.lock().unwrap()
+    }
+
+    /// Waits for the next event.
+    pub fn recv(&self) -> Option<ElectionEvent> {
+        self.events.recv().ok()
+    }
+
+    /// Returns `None` if no event happened within `timeout`.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<ElectionEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    pub fn try_recv(&self) -> Option<ElectionEvent> {
+        self.events.try_recv().ok()
+    }
+}
+
+impl Drop for Campaign {
+    fn drop(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            let (stopped, cond) = &*self.stop;
+            *stopped.lock().unwrap() = true;
+            cond.notify_all();
+            let _ = thread.join();
+        }
+    }
+}
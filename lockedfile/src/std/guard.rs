@@ -0,0 +1,155 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use crate::{LockMode, SyncPolicy};
+
+/// A file together with the lock taken on it, returned by `OpenOptions::lock`.
+///
+/// Unlike a bare `File`, the guard can run the `sync_on_unlock` policy before the
+/// lock is released: an exclusively locked file is flushed to disk when the guard
+/// is dropped or `unlock`ed, so the next holder never sees data that only lives in
+/// the page cache.
+///
+/// With `poison_on_crash`, an exclusive guard also leaves a marker behind until it is
+/// released cleanly; see `Poisoned`.
+#[derive(Debug)]
+pub struct LockedFile {
+    file: Option<std::fs::File>,
+    path: PathBuf,
+    mode: LockMode,
+    sync: SyncPolicy,
+    sync_parent: bool,
+    poison: Option<PathBuf>,
+    poisoned: bool,
+}
+
+impl LockedFile {
+    pub(crate) fn new(file: std::fs::File, path: PathBuf, mode: LockMode, sync: SyncPolicy, sync_parent: bool) -> Self {
+        Self {
+            file: Some(file),
+            path,
+            mode,
+            sync,
+            sync_parent,
+            poison: None,
+            poisoned: false,
+        }
+    }
+
+    pub(crate) fn set_poison_marker(&mut self, marker: Option<PathBuf>, poisoned: bool) {
+        self.poison = marker;
+        self.poisoned = poisoned;
+    }
+
+    /// Declares a poisoned file recovered, so that the poison marker is removed once
+    /// this exclusive guard is released cleanly.
+    pub fn clear_poison(&mut self) {
+        self.poisoned = false;
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn mode(&self) -> LockMode {
+        self.mode
+    }
+
+    /// Applies the sync policy and releases the lock, reporting sync errors that dropping would ignore.
+    pub fn unlock(mut self) -> std::io::Result<()> {
+        self.release()
+    }
+
+    /// Returns the file without syncing it; the lock is released when the file is closed.
+    ///
+    /// The poison marker is left in place, since the guard can no longer tell whether
+    /// the file is released cleanly, so the file stays poisoned until recovered.
+    pub fn into_inner(mut self) -> std::fs::File {
+        self.poison = None;
+        self.file.take().unwrap()
+    }
+
+    fn release(&mut self) -> std::io::Result<()> {
+        let file = match self.file.take() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        #[cfg(feature = "lockdep")]
+        crate::lockdep::released(&file);
+
+        if self.mode == LockMode::Exclusive {
+            sync(&file, &self.path, self.sync, self.sync_parent)?;
+        }
+
+        // A panicking holder leaves the file poisoned, like a crash would.
+        if !std::thread::panicking() {
+            self.remove_poison_marker()?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_poison_marker(&mut self) -> std::io::Result<()> {
+        match self.poison.take() {
+            Some(marker) if !self.poisoned => std::fs::remove_file(marker),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for LockedFile {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
+
+impl Deref for LockedFile {
+    type Target = std::fs::File;
+
+    fn deref(&self) -> &std::fs::File {
+        self.file.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for LockedFile {
+    fn deref_mut(&mut self) -> &mut std::fs::File {
+        self.file.as_mut().unwrap()
+    }
+}
+
+impl Read for LockedFile {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.deref_mut().read(buf)
+    }
+}
+
+impl Write for LockedFile {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.deref_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.deref_mut().flush()
+    }
+}
+
+impl Seek for LockedFile {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.deref_mut().seek(pos)
+    }
+}
+
+pub(crate) fn sync(file: &std::fs::File, path: &Path, policy: SyncPolicy, sync_parent: bool) -> std::io::Result<()> {
+    match policy {
+        SyncPolicy::None => {},
+        SyncPolicy::Data => file.sync_data()?,
+        SyncPolicy::All => file.sync_all()?,
+    }
+
+    if sync_parent {
+        super::atomic::sync_parent(path)?;
+    }
+
+    Ok(())
+}
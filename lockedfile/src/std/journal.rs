@@ -0,0 +1,212 @@
+use std::io::{Read, Write};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use super::OpenOptions;
+use super::atomic::{sibling, sync_parent};
+
+const MAGIC: &[u8; 4] = b"LFJ1";
+
+/// In-place updates of a file that survive crashes, using a redo journal.
+///
+/// Writes are staged in memory by `write_at`. On `commit`, they are first written
+/// to the sidecar `<name>.journal` and synced, then applied to the file, which is
+/// synced in turn before the journal is cleared. Only the written ranges are
+/// journaled, so the file is never copied as a whole.
+///
+/// The file is locked exclusively for the lifetime of the value. Whoever takes the
+/// lock next replays a complete journal left by a crashed writer, or discards an
+/// incomplete one (in which case the file was not touched yet). Readers can call
+/// `recover` before opening the file with `SharedFile`.
+///
+/// The journal consists of the magic `LFJ1`, the number of records as a little-endian
+/// u64, the records (offset and length as little-endian u64, then the data) and a
+/// 64-bit FNV-1a checksum of everything before it.
+pub struct JournaledFile {
+    file: std::fs::File,
+    path: PathBuf,
+    journal_path: PathBuf,
+    pending: Vec<(u64, Vec<u8>)>,
+    recovered: bool,
+}
+
+impl JournaledFile {
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open_exclusive(&path)?;
+        let journal_path = journal_path(&path);
+        let recovered = replay(&file, &journal_path)?;
+
+        Ok(Self {
+            file,
+            path,
+            journal_path,
+            pending: Vec::new(),
+            recovered,
+        })
+    }
+
+    /// Whether opening the file replayed a journal left by a crashed writer.
+    pub fn recovered(&self) -> bool {
+        self.recovered
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The locked file, for reading. Staged writes are not visible before `commit`.
+    pub fn file(&self) -> &std::fs::File {
+        &self.file
+    }
+
+    pub fn write_at(&mut self, offset: u64, data: &[u8]) -> &mut Self {
+        self.pending.push((offset, data.to_vec()));
+
+        self
+    }
+
+    pub fn commit(&mut self) -> std::io::Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let created = !self.journal_path.exists();
+        let mut journal = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&self.journal_path)?;
+        journal.write_all(&encode(&self.pending))?;
+        journal.sync_all()?;
+        if created {
+            sync_parent(&self.journal_path)?;
+        }
+
+        apply(&self.file, &self.pending)?;
+        self.pending.clear();
+
+        journal.set_len(0)?;
+        journal.sync_all()
+    }
+}
+
+/// Replays or discards the journal of `path` under an exclusive lock.
+///
+/// Returns true if a complete journal was replayed.
+pub fn recover<P: AsRef<Path>>(path: P) -> std::io::Result<bool> {
+    let journal_path = journal_path(path.as_ref());
+    if std::fs::metadata(&journal_path).map(|meta| meta.len() == 0).unwrap_or(true) {
+        return Ok(false);
+    }
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open_exclusive(path)?;
+
+    replay(&file, &journal_path)
+}
+
+fn journal_path(path: &Path) -> PathBuf {
+    sibling(path, ".journal")
+}
+
+fn replay(file: &std::fs::File, journal_path: &Path) -> std::io::Result<bool> {
+    let mut journal = match std::fs::OpenOptions::new().read(true).write(true).open(journal_path) {
+        Ok(journal) => journal,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    let mut buf = Vec::new();
+    journal.read_to_end(&mut buf)?;
+    if buf.is_empty() {
+        return Ok(false);
+    }
+
+    let replayed = match decode(&buf) {
+        Some(records) => {
+            apply(file, &records)?;
+            true
+        },
+        None => false,
+    };
+
+    journal.set_len(0)?;
+    journal.sync_all()?;
+
+    Ok(replayed)
+}
+
+fn apply(file: &std::fs::File, records: &[(u64, Vec<u8>)]) -> std::io::Result<()> {
+    for (offset, data) in records {
+        file.write_all_at(data, *offset)?;
+    }
+
+    file.sync_data()
+}
+
+fn encode(records: &[(u64, Vec<u8>)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(MAGIC);
+    buf.extend_from_slice(&(records.len() as u64).to_le_bytes());
+    for (offset, data) in records {
+        buf.extend_from_slice(&offset.to_le_bytes());
+        buf.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        buf.extend_from_slice(data);
+    }
+    let checksum = fnv1a(&buf);
+    buf.extend_from_slice(&checksum.to_le_bytes());
+
+    buf
+}
+
+fn decode(buf: &[u8]) -> Option<Vec<(u64, Vec<u8>)>> {
+    if buf.len() < MAGIC.len() + 16 || &buf[..MAGIC.len()] != MAGIC {
+        return None;
+    }
+
+    let (body, checksum) = buf.split_at(buf.len() - 8);
+    if fnv1a(body) != read_u64(checksum)? {
+        return None;
+    }
+
+    let mut rest = &body[MAGIC.len()..];
+    let count = take_u64(&mut rest)?;
+    let mut records = Vec::new();
+    for _ in 0..count {
+        let offset = take_u64(&mut rest)?;
+        let len = take_u64(&mut rest)? as usize;
+        if rest.len() < len {
+            return None;
+        }
+        let (data, tail) = rest.split_at(len);
+        records.push((offset, data.to_vec()));
+        rest = tail;
+    }
+
+    Some(records)
+}
+
+fn read_u64(buf: &[u8]) -> Option<u64> {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(buf.get(..8)?);
+
+    Some(u64::from_le_bytes(bytes))
+}
+
+fn take_u64(buf: &mut &[u8]) -> Option<u64> {
+    let value = read_u64(buf)?;
+    *buf = &buf[8..];
+
+    Some(value)
+}
+
+fn fnv1a(buf: &[u8]) -> u64 {
+    buf.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -0,0 +1,329 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::LockMode;
+use crate::sys;
+use super::{AtomicWriter, LockedFile, OpenOptions};
+use super::atomic::lock_path;
+
+const MAGIC: &[u8; 4] = b"LFK1";
+/// Entries with hashed names also store their key, as a little-endian u32 length
+/// and the key, after the expiry time.
+const KEYED_MAGIC: &[u8; 4] = b"LFK2";
+const HEADER_LEN: usize = MAGIC.len() + 8;
+
+/// A key-value store in a directory, with one file per key.
+///
+/// Each key has its own lock on the sidecar `<entry>.lock`: reads take it shared,
+/// writes take it exclusively and replace the entry with `AtomicWriter`. Deleting
+/// an entry also removes its sidecar. Entries start with the magic `LFK1` and their
+/// expiry time in milliseconds since the epoch (0 for none) as a little-endian u64.
+/// Expired entries read as absent and are removed by `purge`.
+///
+/// Keys are stored percent-encoded, with everything but ASCII letters, digits, `-`
+/// and `_` escaped, so entry names never collide with sidecar and temporary files.
+/// Names that would be too long for the filesystem are cut short and end with `~`
+/// and a 128-bit hash of the key instead; those entries start with the magic `LFK2`
+/// and keep their key after the expiry time.
+pub struct KvStore {
+    dir: PathBuf,
+}
+
+impl KvStore {
+    pub fn open<P: AsRef<Path>>(dir: P) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir.as_ref())?;
+
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn get(&self, key: &str) -> std::io::Result<Option<Vec<u8>>> {
+        let path = self.entry_path(key)?;
+        let _lock = match lock_existing(&path, LockMode::Shared)? {
+            Some(lock) => lock,
+            None => return Ok(None),
+        };
+
+        read(&path, key)
+    }
+
+    pub fn put<V: AsRef<[u8]>>(&self, key: &str, value: V) -> std::io::Result<()> {
+        let path = self.entry_path(key)?;
+
+        write(AtomicWriter::new(&path)?, key, value.as_ref(), None)
+    }
+
+    /// Stores an entry that reads as absent once `ttl` has passed.
+    pub fn put_with_ttl<V: AsRef<[u8]>>(&self, key: &str, value: V, ttl: Duration) -> std::io::Result<()> {
+        let path = self.entry_path(key)?;
+
+        write(AtomicWriter::new(&path)?, key, value.as_ref(), Some(SystemTime::now() + ttl))
+    }
+
+    /// Returns whether an entry was deleted.
+    pub fn delete(&self, key: &str) -> std::io::Result<bool> {
+        let path = self.entry_path(key)?;
+        let _lock = lock_exclusive(&path)?;
+
+        remove_with_sidecar(&path)
+    }
+
+    /// Replaces the value of `key` with `new` if it is still `expected`, where `None`
+    /// stands for an absent entry, and returns whether it did.
+    ///
+    /// The new entry has no TTL.
+    pub fn compare_and_swap(&self, key: &str, expected: Option<&[u8]>, new: Option<&[u8]>) -> std::io::Result<bool> {
+        let path = self.entry_path(key)?;
+        let writer = AtomicWriter::new(&path)?;
+
+        if read(&path, key)?.as_deref() != expected {
+            return Ok(false);
+        }
+
+        match new {
+            Some(new) => write(writer, key, new, None)?,
+            None => {
+                remove_with_sidecar(&path)?;
+            },
+        }
+
+        Ok(true)
+    }
+
+    /// The keys of all entries that have not expired, in no particular order.
+    pub fn keys(&self) -> std::io::Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for (name, path) in self.entries()? {
+            let _lock = match lock_existing(&path, LockMode::Shared)? {
+                Some(lock) => lock,
+                None => continue,
+            };
+            match read_entry(&path)? {
+                Some(entry) if !entry.is_expired() => keys.extend(entry.key.or_else(|| decode_key(&name))),
+                _ => {},
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Removes expired entries and returns how many there were.
+    pub fn purge(&self) -> std::io::Result<usize> {
+        let mut purged = 0;
+        for (_, path) in self.entries()? {
+            // An entry deleted since the scan must not get its sidecar back.
+            let _lock = match lock_existing(&path, LockMode::Exclusive)? {
+                Some(lock) => lock,
+                None => continue,
+            };
+            let expired = match read_entry(&path)? {
+                Some(entry) => entry.is_expired(),
+                None => false,
+            };
+            if expired && remove_with_sidecar(&path)? {
+                purged += 1;
+            }
+        }
+
+        Ok(purged)
+    }
+
+    fn entry_path(&self, key: &str) -> std::io::Result<PathBuf> {
+        if key.is_empty() {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "empty key"));
+        }
+
+        Ok(self.dir.join(sys::file_name(key)))
+    }
+
+    fn entries(&self) -> std::io::Result<Vec<(String, PathBuf)>> {
+        let mut entries = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if let Some(name) = entry.file_name().to_str().filter(|name| is_entry_name(name)) {
+                entries.push((name.to_string(), entry.path()));
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+/// An entry as stored on disk.
+struct Entry {
+    /// Only stored for entries with hashed names.
+    key: Option<String>,
+    value: Vec<u8>,
+    expires: Option<SystemTime>,
+}
+
+impl Entry {
+    fn is_expired(&self) -> bool {
+        self.expires.is_some_and(|expires| expires <= SystemTime::now())
+    }
+}
+
+fn lock_exclusive(path: &Path) -> std::io::Result<LockedFile> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true);
+
+    lock_sidecar(path, &options, LockMode::Exclusive)
+}
+
+/// Returns None if the entry has no sidecar, which means that it does not exist either.
+fn lock_existing(path: &Path, mode: LockMode) -> std::io::Result<Option<LockedFile>> {
+    let mut options = OpenOptions::new();
+    options.read(true);
+
+    match lock_sidecar(path, &options, mode) {
+        Ok(file) => Ok(Some(file)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Locks the sidecar of an entry, making sure that the lock was not taken on a sidecar
+/// that `delete` removed after it was opened.
+fn lock_sidecar(path: &Path, options: &OpenOptions, mode: LockMode) -> std::io::Result<LockedFile> {
+    let lock_path = lock_path(path);
+
+    loop {
+        let file = options.lock(&lock_path, mode)?;
+        if sys::is_linked_at(&file, &lock_path)? {
+            return Ok(file);
+        }
+    }
+}
+
+/// Reads the value of an entry for `key` that exists and has not expired.
+fn read(path: &Path, key: &str) -> std::io::Result<Option<Vec<u8>>> {
+    match read_entry(path)? {
+        // A hashed name may belong to another key.
+        Some(entry) if entry.key.as_deref().is_some_and(|stored| stored != key) => Ok(None),
+        Some(entry) if entry.is_expired() => Ok(None),
+        Some(entry) => Ok(Some(entry.value)),
+        None => Ok(None),
+    }
+}
+
+fn read_entry(path: &Path) -> std::io::Result<Option<Entry>> {
+    let mut buf = Vec::new();
+    match std::fs::File::open(path) {
+        Ok(mut file) => file.read_to_end(&mut buf)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let invalid = || std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("not a store entry: {}", path.display()));
+
+    if buf.len() < HEADER_LEN {
+        return Err(invalid());
+    }
+    let keyed = match &buf[..MAGIC.len()] {
+        magic if magic == MAGIC => false,
+        magic if magic == KEYED_MAGIC => true,
+        _ => return Err(invalid()),
+    };
+
+    let mut millis = [0u8; 8];
+    millis.copy_from_slice(&buf[MAGIC.len()..HEADER_LEN]);
+    let expires = match u64::from_le_bytes(millis) {
+        0 => None,
+        millis => Some(UNIX_EPOCH + Duration::from_millis(millis)),
+    };
+
+    let mut value = buf.split_off(HEADER_LEN);
+    let key = if keyed {
+        if value.len() < 4 {
+            return Err(invalid());
+        }
+        let mut len = [0u8; 4];
+        len.copy_from_slice(&value[..4]);
+        let len = u32::from_le_bytes(len) as usize;
+        if value.len() < 4 + len {
+            return Err(invalid());
+        }
+        let rest = value.split_off(4 + len);
+        let key = String::from_utf8(value.split_off(4)).map_err(|_| invalid())?;
+        value = rest;
+        Some(key)
+    } else {
+        None
+    };
+
+    Ok(Some(Entry { key, value, expires }))
+}
+
+fn write(mut writer: AtomicWriter, key: &str, value: &[u8], expires: Option<SystemTime>) -> std::io::Result<()> {
+    let millis = expires
+        .map(|expires| expires.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64)
+        .map(|millis| millis.max(1))
+        .unwrap_or(0);
+
+    if sys::is_hashed_name(&sys::file_name(key)) {
+        writer.write_all(KEYED_MAGIC)?;
+        writer.write_all(&millis.to_le_bytes())?;
+        writer.write_all(&(key.len() as u32).to_le_bytes())?;
+        writer.write_all(key.as_bytes())?;
+    } else {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&millis.to_le_bytes())?;
+    }
+    writer.write_all(value)?;
+
+    writer.commit()
+}
+
+/// Removes an entry and then its sidecar, while holding the lock on the sidecar.
+fn remove_with_sidecar(path: &Path) -> std::io::Result<bool> {
+    let removed = remove(path)?;
+    remove(&lock_path(path))?;
+
+    Ok(removed)
+}
+
+fn remove(path: &Path) -> std::io::Result<bool> {
+    match std::fs::remove_file(path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether `name` is the file name of an entry rather than a sidecar or temporary file.
+pub(crate) fn is_entry_name(name: &str) -> bool {
+    decode_key(name).is_some() || sys::is_hashed_name(name)
+}
+
+/// Returns `None` for names that are not entries, such as sidecar files.
+fn decode_key(name: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(name.len());
+    let mut rest = name.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match byte {
+            b'%' => {
+                let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+                rest = &tail[2..];
+            },
+            b'-' | b'_' => {
+                bytes.push(byte);
+                rest = tail;
+            },
+            _ if byte.is_ascii_alphanumeric() => {
+                bytes.push(byte);
+                rest = tail;
+            },
+            _ => return None,
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
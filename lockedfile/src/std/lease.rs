@@ -0,0 +1,223 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+use crate::lease::{self, LeaseRecord};
+use super::{OpenOptions, SharedFile};
+
+/// A lease on a file that stays valid only while its holder keeps refreshing it.
+///
+/// The holder rewrites the timestamp in the lease file from a background thread.
+/// Other parties take the lease over once the timestamp is older than the TTL,
+/// so a frozen holder loses the lease instead of blocking everyone forever.
+/// Check `is_held` before doing work that must not overlap with another holder.
+pub struct Lease {
+    path: PathBuf,
+    owner: String,
+    ttl: Duration,
+    shared: Arc<Shared>,
+    refresher: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+struct State {
+    refreshed: Instant,
+    lost: bool,
+    stopped: bool,
+}
+
+impl Lease {
+    pub fn acquire<P: AsRef<Path>>(path: P, ttl: Duration) -> std::io::Result<Self> {
+        loop {
+            if let Some(lease) = Self::try_acquire(path.as_ref(), ttl)? {
+                return Ok(lease);
+            }
+
+            std::thread::sleep(lease::poll_interval(ttl));
+        }
+    }
+
+    /// Returns `Ok(None)` if the lease is held by someone else.
+    ///
+    /// The record is read back after a short delay, so that a contender that the lock
+    /// did not keep out, as can happen on network filesystems, is noticed. That only
+    /// narrows the race: two parties are still kept from both taking an expired lease
+    /// by the kernel lock around the read, check and write.
+    pub fn try_acquire<P: AsRef<Path>>(path: P, ttl: Duration) -> std::io::Result<Option<Self>> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = open_lease_file(&path)?;
+
+        if let Some(record) = read_record(&mut file)? {
+            if !record.is_expired(ttl) {
+                return Ok(None);
+            }
+        }
+
+        let acquired = Instant::now();
+        let record = LeaseRecord::new(crate::sys::unique_id());
+        write_record(&mut file, &record)?;
+        drop(file);
+
+        std::thread::sleep(lease::confirm_delay(ttl));
+        let mut file = open_lease_file(&path)?;
+        if read_record(&mut file)?.is_none_or(|current| current.owner != record.owner) {
+            return Ok(None);
+        }
+        drop(file);
+
+        Ok(Some(Self::start(path, record.owner, ttl, acquired)))
+    }
+
+    /// Reads the current record of the lease file without taking the lease.
+    pub fn holder<P: AsRef<Path>>(path: P) -> std::io::Result<Option<LeaseRecord>> {
+        match SharedFile::open(path) {
+            Ok(mut file) => read_record(&mut file),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn start(path: PathBuf, owner: String, ttl: Duration, acquired: Instant) -> Self {
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                refreshed: acquired,
+                lost: false,
+                stopped: false,
+            }),
+            cond: Condvar::new(),
+        });
+
+        let refresher = {
+            let path = path.clone();
+            let owner = owner.clone();
+            let shared = shared.clone();
+            std::thread::spawn(move || refresh_loop(&path, &owner, ttl, &shared))
+        };
+
+        Self {
+            path,
+            owner,
+            ttl,
+            shared,
+            refresher: Some(refresher),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// Returns false once the lease was taken over or could not be refreshed in time,
+    /// which is a little before other parties may take it over.
+    pub fn is_held(&self) -> bool {
+        let state = self.shared.state.lock().unwrap();
+
+        !state.lost && state.refreshed.elapsed() < lease::hold_time(self.ttl)
+    }
+
+    pub fn release(mut self) -> std::io::Result<()> {
+        self.stop()
+    }
+
+    fn stop(&mut self) -> std::io::Result<()> {
+        match self.refresher.take() {
+            Some(refresher) => {
+                self.shared.state.lock().unwrap().stopped = true;
+                self.shared.cond.notify_all();
+                refresher.join()
+                    .unwrap_or_else(|_| Err(std::io::Error::other("lease refresher panicked")))
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+fn refresh_loop(path: &Path, owner: &str, ttl: Duration, shared: &Shared) -> std::io::Result<()> {
+    let interval = lease::refresh_interval(ttl);
+
+    loop {
+        {
+            let state = shared.state.lock().unwrap();
+            let (state, _) = shared.cond
+                .wait_timeout_while(state, interval, |state| !state.stopped)
+                .unwrap();
+            if state.stopped {
+                break;
+            }
+        }
+
+        match refresh(path, owner) {
+            Ok(Some(refreshed)) => {
+                shared.state.lock().unwrap().refreshed = refreshed;
+            },
+            Ok(None) => {
+                shared.state.lock().unwrap().lost = true;
+                return Ok(());
+            },
+            Err(_) => {},
+        }
+    }
+
+    clear(path, owner)
+}
+
+/// Returns when the refresh started, or `None` if the lease was taken over.
+fn refresh(path: &Path, owner: &str) -> std::io::Result<Option<Instant>> {
+    let mut file = open_lease_file(path)?;
+
+    match read_record(&mut file)? {
+        Some(record) if record.owner == owner => {
+            let refreshed = Instant::now();
+            write_record(&mut file, &LeaseRecord::new(owner.to_owned()))?;
+            Ok(Some(refreshed))
+        },
+        _ => Ok(None),
+    }
+}
+
+fn clear(path: &Path, owner: &str) -> std::io::Result<()> {
+    let mut file = open_lease_file(path)?;
+
+    match read_record(&mut file)? {
+        Some(record) if record.owner == owner => file.set_len(0),
+        _ => Ok(()),
+    }
+}
+
+fn open_lease_file(path: &Path) -> std::io::Result<std::fs::File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open_exclusive(path)
+}
+
+fn read_record(file: &mut std::fs::File) -> std::io::Result<Option<LeaseRecord>> {
+    let mut buf = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut buf)?;
+
+    Ok(LeaseRecord::from_bytes(&buf))
+}
+
+fn write_record(file: &mut std::fs::File, record: &LeaseRecord) -> std::io::Result<()> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&record.to_bytes())?;
+    file.sync_data()
+}
@@ -0,0 +1,173 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::{LockMode, SyncPolicy};
+use crate::lockset::{self, Entry};
+use super::OpenOptions;
+use super::guard::sync;
+
+/// Locks several files at once without deadlocking against other lock sets.
+///
+/// Files are locked in the order of their (device, inode) pair, which is the same
+/// in every process. If any lock fails or times out, the locks taken so far are released.
+/// So are they if a path turns out to name another file once it is locked, e.g. because
+/// it was replaced after it was sorted; the files are then sorted and locked again.
+#[derive(Debug,Clone,Default)]
+pub struct LockSet {
+    requests: Vec<(PathBuf, LockMode)>,
+    create: bool,
+    timeout: Option<Duration>,
+    sync: SyncPolicy,
+}
+
+impl LockSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn shared<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.add(path, LockMode::Shared)
+    }
+
+    pub fn exclusive<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.add(path, LockMode::Exclusive)
+    }
+
+    pub fn add<P: AsRef<Path>>(&mut self, path: P, mode: LockMode) -> &mut Self {
+        self.requests.push((path.as_ref().to_path_buf(), mode));
+
+        self
+    }
+
+    /// Creates missing files before locking them.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+
+        self
+    }
+
+    /// Flushes the exclusively locked files to disk before the guard releases them.
+    pub fn sync_on_unlock(&mut self, policy: SyncPolicy) -> &mut Self {
+        self.sync = policy;
+
+        self
+    }
+
+    /// Bounds the time spent waiting for all locks together.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+
+        self
+    }
+
+    pub fn lock(&self) -> std::io::Result<LockSetGuard> {
+        let deadline = self.timeout.map(|timeout| std::time::Instant::now() + timeout);
+
+        loop {
+            if let Some(files) = self.try_plan(deadline)? {
+                return Ok(LockSetGuard {
+                    files,
+                    sync: self.sync,
+                });
+            }
+        }
+    }
+
+    /// Locks the files in the planned order, or releases them and returns None if a
+    /// path was replaced after planning.
+    fn try_plan(&self, deadline: Option<std::time::Instant>) -> std::io::Result<Option<Vec<LockedEntry>>> {
+        let entries = lockset::plan(&self.requests, self.create)?;
+        let mut files = Vec::with_capacity(entries.len());
+
+        for Entry { key, paths, mode } in entries {
+            let options = entry_options(mode);
+            let file = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                    options.open_timeout(&paths[0], mode, remaining)?
+                },
+                None => options.open_with(&paths[0], mode)?,
+            };
+            if !lockset::is_current(key, &paths, &file.metadata()?)? {
+                return Ok(None);
+            }
+
+            files.push(LockedEntry { paths, mode, file });
+        }
+
+        Ok(Some(files))
+    }
+}
+
+fn entry_options(mode: LockMode) -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.read(true);
+    if mode == LockMode::Exclusive {
+        options.write(true);
+    }
+
+    options
+}
+
+struct LockedEntry {
+    paths: Vec<PathBuf>,
+    mode: LockMode,
+    file: std::fs::File,
+}
+
+/// Holds every lock of a `LockSet`; all of them are released when it is dropped.
+///
+/// Exclusively locked files are opened for reading and writing, shared ones for reading.
+pub struct LockSetGuard {
+    files: Vec<LockedEntry>,
+    sync: SyncPolicy,
+}
+
+impl LockSetGuard {
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&std::fs::File> {
+        self.find(path.as_ref()).map(|i| &self.files[i].file)
+    }
+
+    pub fn get_mut<P: AsRef<Path>>(&mut self, path: P) -> Option<&mut std::fs::File> {
+        self.find(path.as_ref()).map(move |i| &mut self.files[i].file)
+    }
+
+    pub fn mode<P: AsRef<Path>>(&self, path: P) -> Option<LockMode> {
+        self.find(path.as_ref()).map(|i| self.files[i].mode)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Applies the sync policy and releases every lock, reporting the first sync error.
+    pub fn unlock(mut self) -> std::io::Result<()> {
+        self.release()
+    }
+
+    fn find(&self, path: &Path) -> Option<usize> {
+        self.files.iter().position(|entry| entry.paths.iter().any(|p| p == path))
+    }
+
+    fn release(&mut self) -> std::io::Result<()> {
+        let mut result = Ok(());
+
+        for entry in self.files.drain(..) {
+            if entry.mode == LockMode::Exclusive {
+                let synced = sync(&entry.file, &entry.paths[0], self.sync, false);
+                result = result.and(synced);
+            }
+        }
+
+        result
+    }
+}
+
+impl Drop for LockSetGuard {
+    fn drop(&mut self) {
+        let _ = self.release();
+    }
+}
@@ -0,0 +1,91 @@
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use crate::LockMode;
+use super::LockedFile;
+
+/// A read-only memory map of a locked file, which cannot outlive the lock.
+pub struct LockedMmap<'a> {
+    map: memmap2::Mmap,
+    _guard: PhantomData<&'a mut LockedFile>,
+}
+
+/// A mutable memory map of an exclusively locked file, which cannot outlive the lock.
+pub struct LockedMmapMut<'a> {
+    map: memmap2::MmapMut,
+    _guard: PhantomData<&'a mut LockedFile>,
+}
+
+impl LockedFile {
+    /// Maps the whole file read-only.
+    ///
+    /// The map borrows the guard mutably, so it cannot outlive the lock, and the file
+    /// cannot be truncated or written through the guard while the map exists:
+    ///
+    /// ```compile_fail,E0502
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut guard = lockedfile::std::OwnedFile::lock("index")?;
+    /// let map = guard.map()?;
+    /// guard.set_len(0)?;
+    /// assert_eq!(map.len(), 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// Accessing the map would still fault if another process truncated the file,
+    /// which cannot happen as long as everyone that writes the file takes the
+    /// exclusive lock.
+    pub fn map(&mut self) -> std::io::Result<LockedMmap<'_>> {
+        let map = unsafe { memmap2::Mmap::map(&**self)? };
+
+        Ok(LockedMmap {
+            map,
+            _guard: PhantomData,
+        })
+    }
+
+    /// Maps the whole file for writing; fails with `ErrorKind::PermissionDenied`
+    /// unless the lock is exclusive. The file must be open for reading and writing.
+    pub fn map_mut(&mut self) -> std::io::Result<LockedMmapMut<'_>> {
+        if self.mode() != LockMode::Exclusive {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::PermissionDenied,
+                format!("mutable maps need an exclusive lock: {}", self.path().display())));
+        }
+
+        let map = unsafe { memmap2::MmapMut::map_mut(&**self)? };
+
+        Ok(LockedMmapMut {
+            map,
+            _guard: PhantomData,
+        })
+    }
+}
+
+impl Deref for LockedMmap<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl LockedMmapMut<'_> {
+    /// Writes modified pages back to the file.
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.map.flush()
+    }
+}
+
+impl Deref for LockedMmapMut<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map
+    }
+}
+
+impl DerefMut for LockedMmapMut<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.map
+    }
+}
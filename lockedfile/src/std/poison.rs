@@ -0,0 +1,77 @@
+use std::path::{Path, PathBuf};
+use super::LockedFile;
+
+/// Returned, wrapped in an `io::Error`, by `OpenOptions::lock` and `try_lock` when the
+/// previous exclusive holder of the file did not release it cleanly.
+///
+/// The guard inside still holds the lock. The file stays poisoned, also for later
+/// holders, until an exclusive guard calls `LockedFile::clear_poison` and is then
+/// released cleanly, so a crash during recovery leaves it poisoned.
+#[derive(Debug)]
+pub struct Poisoned {
+    guard: LockedFile,
+}
+
+impl Poisoned {
+    pub(crate) fn new(guard: LockedFile) -> Self {
+        Self { guard }
+    }
+
+    /// Extracts the `Poisoned` value from an error returned by `lock` or `try_lock`,
+    /// or gives the error back if it has another cause.
+    pub fn from_io_error(e: std::io::Error) -> Result<Self, std::io::Error> {
+        if !e.get_ref().is_some_and(|inner| inner.is::<Poisoned>()) {
+            return Err(e);
+        }
+
+        match e.into_inner().unwrap().downcast::<Poisoned>() {
+            Ok(poisoned) => Ok(*poisoned),
+            Err(_) => unreachable!(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &LockedFile {
+        &self.guard
+    }
+
+    pub fn get_mut(&mut self) -> &mut LockedFile {
+        &mut self.guard
+    }
+
+    pub fn into_inner(self) -> LockedFile {
+        self.guard
+    }
+}
+
+impl std::fmt::Display for Poisoned {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: previous writer did not release the lock cleanly", self.guard.path().display())
+    }
+}
+
+impl std::error::Error for Poisoned {}
+
+/// The sidecar file that exists while `target` is held by an exclusive guard.
+pub(crate) fn marker_path(target: &Path) -> PathBuf {
+    super::atomic::sibling(target, ".poison")
+}
+
+/// Checks the marker of `target` and, for an exclusive guard, sets it.
+///
+/// Returns whether the file was poisoned and the marker an exclusive guard clears.
+pub(crate) fn enter(target: &Path, exclusive: bool) -> std::io::Result<(bool, Option<PathBuf>)> {
+    let marker = marker_path(target);
+    let poisoned = marker.exists();
+
+    if !exclusive {
+        return Ok((poisoned, None));
+    }
+
+    // The marker must outlive a crash of the system too, or the file would come back unpoisoned.
+    if !poisoned {
+        std::fs::File::create(&marker)?.sync_all()?;
+        super::atomic::sync_parent(&marker)?;
+    }
+
+    Ok((poisoned, Some(marker)))
+}
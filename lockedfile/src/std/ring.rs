@@ -0,0 +1,300 @@
+use std::convert::TryFrom;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::sync::atomic::{fence, Ordering};
+use crate::sys::range_lock;
+
+const MAGIC: &[u8; 4] = b"LFR1";
+const HEADER_LEN: u64 = 64;
+const CAPACITY: usize = 8;
+const HEAD: usize = 16;
+const TAIL: usize = 24;
+const RECORD_HEADER_LEN: u64 = 4;
+
+/// A fixed-size ring buffer of records in a file, for telemetry between processes.
+///
+/// The file starts with a 64-byte header holding the capacity of the data region
+/// and the positions of its newest and oldest bytes, counted from the first byte
+/// ever written. Records are a little-endian u32 length followed by the data, and
+/// wrap around the end of the data region. When the buffer is full, the oldest
+/// records are overwritten.
+///
+/// Writers hold an exclusive record lock on the header while they append, and an
+/// exclusive lock on the range they overwrite. Readers keep their own position and
+/// hold a shared lock on the range of the record they copy, so a writer never
+/// overwrites a record while it is being read. The positions are updated only after
+/// the data, so a writer that dies halfway leaves the buffer consistent.
+///
+/// The record locks are open file description locks, so writers and readers exclude
+/// each other within a process too. They only exist on Linux; elsewhere, opening a
+/// ring buffer fails with `ErrorKind::Unsupported`.
+///
+/// Put the file on a memory-backed filesystem such as `/dev/shm` to avoid disk I/O.
+pub struct RingBuffer {
+    file: std::fs::File,
+    map: memmap2::MmapMut,
+    capacity: u64,
+}
+
+impl RingBuffer {
+    /// Opens the ring buffer at `path`, creating it with `capacity` bytes of data if it is empty.
+    ///
+    /// An existing ring buffer keeps its capacity.
+    pub fn create<P: AsRef<Path>>(path: P, capacity: u64) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path.as_ref())?;
+
+        range_lock(&file, libc::F_WRLCK, 0, HEADER_LEN)?;
+        let result = init(&file, capacity);
+        range_lock(&file, libc::F_UNLCK, 0, HEADER_LEN)?;
+        result?;
+
+        Self::open(path)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path.as_ref())?;
+
+        range_lock(&file, libc::F_RDLCK, 0, HEADER_LEN)?;
+        let map = unsafe { memmap2::MmapMut::map_mut(&file) };
+        range_lock(&file, libc::F_UNLCK, 0, HEADER_LEN)?;
+        let map = map?;
+        let capacity = check_header(&map, path.as_ref())?;
+
+        Ok(Self { file, map, capacity })
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    /// Appends a record, overwriting the oldest ones if needed.
+    ///
+    /// Fails with `ErrorKind::InvalidInput` if the record does not fit into the buffer at all.
+    pub fn push<R: AsRef<[u8]>>(&mut self, record: R) -> std::io::Result<()> {
+        let record = record.as_ref();
+        let len = u32::try_from(record.len()).ok()
+            .filter(|&len| RECORD_HEADER_LEN + len as u64 <= self.capacity)
+            .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "record does not fit into the ring buffer"))?;
+
+        range_lock(&self.file, libc::F_WRLCK, 0, HEADER_LEN)?;
+        let result = self.push_locked(len, record);
+        range_lock(&self.file, libc::F_UNLCK, 0, HEADER_LEN)?;
+
+        result
+    }
+
+    fn push_locked(&mut self, len: u32, record: &[u8]) -> std::io::Result<()> {
+        let head = read_u64(&self.map, HEAD);
+        let mut tail = read_u64(&self.map, TAIL);
+        let new_head = head + RECORD_HEADER_LEN + len as u64;
+
+        while new_head - tail > self.capacity {
+            let mut buf = [0u8; RECORD_HEADER_LEN as usize];
+            self.read_data(tail, &mut buf);
+            tail += RECORD_HEADER_LEN + u32::from_le_bytes(buf) as u64;
+        }
+        write_u64(&mut self.map, TAIL, tail);
+        fence(Ordering::SeqCst);
+
+        let ranges = data_ranges(self.capacity, head, new_head - head);
+        for &(start, len) in &ranges {
+            range_lock(&self.file, libc::F_WRLCK, start, len)?;
+        }
+
+        self.write_data(head, &len.to_le_bytes());
+        self.write_data(head + RECORD_HEADER_LEN, record);
+        fence(Ordering::SeqCst);
+        write_u64(&mut self.map, HEAD, new_head);
+
+        for &(start, len) in &ranges {
+            range_lock(&self.file, libc::F_UNLCK, start, len)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_data(&self, pos: u64, buf: &mut [u8]) {
+        copy_out(&self.map, self.capacity, pos, buf);
+    }
+
+    fn write_data(&mut self, pos: u64, buf: &[u8]) {
+        let data = &mut self.map[HEADER_LEN as usize..];
+        let offset = (pos % self.capacity) as usize;
+        let first = std::cmp::min(buf.len(), data.len() - offset);
+        data[offset..offset + first].copy_from_slice(&buf[..first]);
+        data[..buf.len() - first].copy_from_slice(&buf[first..]);
+    }
+}
+
+/// Reads the records of a `RingBuffer` from its own position.
+pub struct RingReader {
+    file: std::fs::File,
+    map: memmap2::Mmap,
+    capacity: u64,
+    position: u64,
+    skipped: u64,
+}
+
+impl RingReader {
+    /// Starts reading after the newest record.
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = std::fs::File::open(path.as_ref())?;
+
+        range_lock(&file, libc::F_RDLCK, 0, HEADER_LEN)?;
+        let map = unsafe { memmap2::Mmap::map(&file) };
+        range_lock(&file, libc::F_UNLCK, 0, HEADER_LEN)?;
+        let map = map?;
+        let capacity = check_header(&map, path.as_ref())?;
+
+        let mut reader = Self {
+            file,
+            map,
+            capacity,
+            position: 0,
+            skipped: 0,
+        };
+        reader.position = reader.positions()?.0;
+
+        Ok(reader)
+    }
+
+    /// Moves back to the oldest record that has not been overwritten yet.
+    pub fn seek_oldest(&mut self) -> std::io::Result<()> {
+        self.position = self.positions()?.1;
+
+        Ok(())
+    }
+
+    /// The number of bytes of records that were overwritten before this reader got to them.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Returns the next record, or `None` if the reader has caught up with the writers.
+    pub fn next_record(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        range_lock(&self.file, libc::F_RDLCK, 0, HEADER_LEN)?;
+        let head = read_u64(&self.map, HEAD);
+        let tail = read_u64(&self.map, TAIL);
+
+        if self.position < tail {
+            self.skipped += tail - self.position;
+            self.position = tail;
+        }
+        if self.position >= head {
+            range_lock(&self.file, libc::F_UNLCK, 0, HEADER_LEN)?;
+            return Ok(None);
+        }
+
+        let mut len = [0u8; RECORD_HEADER_LEN as usize];
+        copy_out(&self.map, self.capacity, self.position, &mut len);
+        let len = u32::from_le_bytes(len) as u64;
+
+        // Lock the record before letting writers in, so they cannot overwrite it.
+        let ranges = data_ranges(self.capacity, self.position, RECORD_HEADER_LEN + len);
+        let locked = lock_all(&self.file, &ranges);
+        range_lock(&self.file, libc::F_UNLCK, 0, HEADER_LEN)?;
+        locked?;
+
+        let mut record = vec![0u8; len as usize];
+        copy_out(&self.map, self.capacity, self.position + RECORD_HEADER_LEN, &mut record);
+        self.position += RECORD_HEADER_LEN + len;
+
+        for &(start, len) in &ranges {
+            range_lock(&self.file, libc::F_UNLCK, start, len)?;
+        }
+
+        Ok(Some(record))
+    }
+
+    /// Returns the positions of the newest and oldest bytes.
+    fn positions(&self) -> std::io::Result<(u64, u64)> {
+        range_lock(&self.file, libc::F_RDLCK, 0, HEADER_LEN)?;
+        let positions = (read_u64(&self.map, HEAD), read_u64(&self.map, TAIL));
+        range_lock(&self.file, libc::F_UNLCK, 0, HEADER_LEN)?;
+
+        Ok(positions)
+    }
+}
+
+fn init(file: &std::fs::File, capacity: u64) -> std::io::Result<()> {
+    if file.metadata()?.len() != 0 {
+        return Ok(());
+    }
+    if capacity <= RECORD_HEADER_LEN {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "ring buffer capacity is too small"));
+    }
+
+    let mut header = [0u8; HEADER_LEN as usize];
+    header[..MAGIC.len()].copy_from_slice(MAGIC);
+    header[CAPACITY..CAPACITY + 8].copy_from_slice(&capacity.to_le_bytes());
+
+    file.set_len(HEADER_LEN + capacity)?;
+    file.write_all_at(&header, 0)
+}
+
+fn check_header(map: &[u8], path: &Path) -> std::io::Result<u64> {
+    let valid = map.len() as u64 > HEADER_LEN && &map[..MAGIC.len()] == MAGIC;
+    let capacity = if valid { read_u64(map, CAPACITY) } else { 0 };
+
+    if !valid || HEADER_LEN + capacity != map.len() as u64 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("not a ring buffer: {}", path.display())));
+    }
+
+    Ok(capacity)
+}
+
+/// Locks the ranges shared, releasing the ones already taken if one fails.
+fn lock_all(file: &std::fs::File, ranges: &[(u64, u64)]) -> std::io::Result<()> {
+    for (i, &(start, len)) in ranges.iter().enumerate() {
+        if let Err(e) = range_lock(file, libc::F_RDLCK, start, len) {
+            for &(start, len) in &ranges[..i] {
+                let _ = range_lock(file, libc::F_UNLCK, start, len);
+            }
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+/// The file ranges of `len` bytes of data starting at position `pos`, split where they wrap around.
+fn data_ranges(capacity: u64, pos: u64, len: u64) -> Vec<(u64, u64)> {
+    let offset = pos % capacity;
+    let first = std::cmp::min(len, capacity - offset);
+    let mut ranges = vec![(HEADER_LEN + offset, first)];
+    if first < len {
+        ranges.push((HEADER_LEN, len - first));
+    }
+
+    ranges
+}
+
+fn copy_out(map: &[u8], capacity: u64, pos: u64, buf: &mut [u8]) {
+    let data = &map[HEADER_LEN as usize..];
+    let offset = (pos % capacity) as usize;
+    let first = std::cmp::min(buf.len(), data.len() - offset);
+    buf[..first].copy_from_slice(&data[offset..offset + first]);
+    let rest = buf.len() - first;
+    buf[first..].copy_from_slice(&data[..rest]);
+}
+
+fn read_u64(map: &[u8], offset: usize) -> u64 {
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&map[offset..offset + 8]);
+
+    u64::from_le_bytes(buf)
+}
+
+fn write_u64(map: &mut [u8], offset: usize, value: u64) {
+    map[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+}
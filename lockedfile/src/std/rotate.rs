@@ -0,0 +1,216 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use super::OpenOptions;
+use super::atomic::{lock_path, sibling};
+
+/// When a `RotatingLog` starts a new file.
+///
+/// Without a size or age limit, the log is never rotated.
+#[derive(Debug,Clone)]
+pub struct Rotation {
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+    keep: usize,
+}
+
+impl Default for Rotation {
+    fn default() -> Self {
+        Self {
+            max_size: None,
+            max_age: None,
+            keep: 5,
+        }
+    }
+}
+
+impl Rotation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rotates before an append that would make the file larger than `max_size` bytes.
+    pub fn max_size(&mut self, max_size: u64) -> &mut Self {
+        self.max_size = Some(max_size);
+
+        self
+    }
+
+    /// Rotates before the first append after the file became older than `max_age`.
+    pub fn max_age(&mut self, max_age: Duration) -> &mut Self {
+        self.max_age = Some(max_age);
+
+        self
+    }
+
+    /// How many rotated files (`<name>.1` to `<name>.<keep>`) are kept; defaults to 5.
+    pub fn keep(&mut self, keep: usize) -> &mut Self {
+        self.keep = keep;
+
+        self
+    }
+
+    pub fn open<P: AsRef<Path>>(&self, path: P) -> std::io::Result<RotatingLog> {
+        let path = path.as_ref().to_path_buf();
+        let mut lock = lock(&path)?;
+        let file = open(&path)?;
+        created(&mut lock)?;
+
+        Ok(RotatingLog {
+            path,
+            rotation: self.clone(),
+            id: id(&file.metadata()?),
+            file,
+        })
+    }
+}
+
+/// A log file that several processes append to and rotate together.
+///
+/// Every append takes an exclusive lock on the sidecar `<name>.lock`, which is not
+/// renamed by rotation. Under the lock, the writer reopens the log if its inode
+/// changed since the last append (another writer rotated it), then rotates the
+/// log itself if it reached a limit, and finally appends, so no line ends up in a
+/// file that was already rotated away. The sidecar also records when the current
+/// file was started, for `Rotation::max_age`.
+pub struct RotatingLog {
+    path: PathBuf,
+    rotation: Rotation,
+    file: std::fs::File,
+    id: (u64, u64),
+}
+
+impl RotatingLog {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends `buf` as a whole, never splitting it across two files.
+    pub fn append(&mut self, buf: &[u8]) -> std::io::Result<()> {
+        let mut lock = lock(&self.path)?;
+
+        match std::fs::metadata(&self.path) {
+            Ok(meta) if id(&meta) == self.id => {},
+            Ok(_) => self.reopen()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => self.reopen()?,
+            Err(e) => return Err(e),
+        }
+
+        if self.is_due(&mut lock, buf.len() as u64)? {
+            self.rotate()?;
+            self.reopen()?;
+            reset_created(&mut lock)?;
+        }
+
+        self.file.write_all(buf)
+    }
+
+    fn is_due(&self, lock: &mut std::fs::File, len: u64) -> std::io::Result<bool> {
+        let size = self.file.metadata()?.len();
+        if size == 0 {
+            return Ok(false);
+        }
+
+        if let Some(max_size) = self.rotation.max_size {
+            if size + len > max_size {
+                return Ok(true);
+            }
+        }
+
+        if let Some(max_age) = self.rotation.max_age {
+            let age = SystemTime::now().duration_since(created(lock)?).unwrap_or_default();
+            if age >= max_age {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn rotate(&self) -> std::io::Result<()> {
+        let keep = self.rotation.keep;
+        if keep == 0 {
+            return ignore_missing(std::fs::remove_file(&self.path));
+        }
+
+        ignore_missing(std::fs::remove_file(rotated(&self.path, keep)))?;
+        for i in (1..keep).rev() {
+            ignore_missing(std::fs::rename(rotated(&self.path, i), rotated(&self.path, i + 1)))?;
+        }
+
+        std::fs::rename(&self.path, rotated(&self.path, 1))
+    }
+
+    fn reopen(&mut self) -> std::io::Result<()> {
+        self.file = open(&self.path)?;
+        self.id = id(&self.file.metadata()?);
+
+        Ok(())
+    }
+}
+
+impl Write for RotatingLog {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.append(buf)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn lock(path: &Path) -> std::io::Result<std::fs::File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open_exclusive(lock_path(path))
+}
+
+fn open(path: &Path) -> std::io::Result<std::fs::File> {
+    std::fs::OpenOptions::new()
+        .append(true)
+        .create(true)
+        .open(path)
+}
+
+fn id(meta: &std::fs::Metadata) -> (u64, u64) {
+    (meta.dev(), meta.ino())
+}
+
+fn rotated(path: &Path, i: usize) -> PathBuf {
+    sibling(path, &format!(".{}", i))
+}
+
+/// Reads when the current file was started from the lock file, recording now if it is unset.
+fn created(lock: &mut std::fs::File) -> std::io::Result<SystemTime> {
+    let mut buf = String::new();
+    lock.seek(SeekFrom::Start(0))?;
+    lock.read_to_string(&mut buf)?;
+
+    match buf.trim().parse::<u64>() {
+        Ok(millis) => Ok(UNIX_EPOCH + Duration::from_millis(millis)),
+        Err(_) => reset_created(lock),
+    }
+}
+
+fn reset_created(lock: &mut std::fs::File) -> std::io::Result<SystemTime> {
+    let now = SystemTime::now();
+    let millis = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+
+    lock.set_len(0)?;
+    lock.seek(SeekFrom::Start(0))?;
+    writeln!(lock, "{}", millis)?;
+
+    Ok(now)
+}
+
+fn ignore_missing(result: std::io::Result<()>) -> std::io::Result<()> {
+    match result {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
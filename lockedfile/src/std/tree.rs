@@ -0,0 +1,216 @@
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use crate::LockMode;
+use crate::sys::{self, Backoff};
+use super::OpenOptions;
+
+/// Lock modes of the multi-granularity protocol.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+enum Intent {
+    IntentionShared,
+    IntentionExclusive,
+    Shared,
+    Exclusive,
+}
+
+impl Intent {
+    fn compatible(self, other: Intent) -> bool {
+        use Intent::*;
+
+        match (self, other) {
+            (Exclusive, _) | (_, Exclusive) => false,
+            (IntentionShared, _) | (_, IntentionShared) => true,
+            (IntentionExclusive, IntentionExclusive) => true,
+            (Shared, Shared) => true,
+            _ => false,
+        }
+    }
+
+    fn tag(self) -> &'static str {
+        match self {
+            Intent::IntentionShared => "IS",
+            Intent::IntentionExclusive => "IX",
+            Intent::Shared => "S",
+            Intent::Exclusive => "X",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Self> {
+        match tag {
+            "IS" => Some(Intent::IntentionShared),
+            "IX" => Some(Intent::IntentionExclusive),
+            "S" => Some(Intent::Shared),
+            "X" => Some(Intent::Exclusive),
+            _ => None,
+        }
+    }
+}
+
+/// A multi-granularity lock manager over a tree of paths.
+///
+/// Locking a node locks its whole subtree. Before a node is locked, every ancestor
+/// (including the root, the empty path) is locked with the matching intention mode,
+/// so an exclusive lock on `a/b` conflicts with any lock on `a/b/c` and vice versa,
+/// while locks on `a/b/c` and `a/d` do not conflict.
+///
+/// The lock state lives in `lock_dir`, which must not be inside the locked tree.
+/// Each holder registers itself with a file it keeps locked, so holders that die
+/// are cleaned up by the next process that looks at the node.
+pub struct LockTree {
+    lock_dir: PathBuf,
+}
+
+impl LockTree {
+    pub fn new<P: AsRef<Path>>(lock_dir: P) -> std::io::Result<Self> {
+        std::fs::create_dir_all(lock_dir.as_ref())?;
+
+        Ok(Self {
+            lock_dir: lock_dir.as_ref().to_path_buf(),
+        })
+    }
+
+    pub fn lock<P: AsRef<Path>>(&self, path: P, mode: LockMode) -> std::io::Result<TreeGuard> {
+        self.lock_inner(path.as_ref(), mode, Backoff::forever())
+    }
+
+    /// Fails with `ErrorKind::TimedOut` if the node and its ancestors cannot be locked within `timeout`.
+    pub fn lock_timeout<P: AsRef<Path>>(&self, path: P, mode: LockMode, timeout: Duration) -> std::io::Result<TreeGuard> {
+        self.lock_inner(path.as_ref(), mode, Backoff::new(timeout))
+    }
+
+    /// Returns `Ok(None)` if a conflicting lock is held.
+    pub fn try_lock<P: AsRef<Path>>(&self, path: P, mode: LockMode) -> std::io::Result<Option<TreeGuard>> {
+        match self.lock_inner(path.as_ref(), mode, Backoff::new(Duration::from_secs(0))) {
+            Ok(guard) => Ok(Some(guard)),
+            Err(e) if e.kind() == std::io::ErrorKind::TimedOut => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn lock_inner(&self, path: &Path, mode: LockMode, mut backoff: Backoff) -> std::io::Result<TreeGuard> {
+        let nodes = ancestors(path)?;
+        let (intention, target) = match mode {
+            LockMode::Shared => (Intent::IntentionShared, Intent::Shared),
+            LockMode::Exclusive => (Intent::IntentionExclusive, Intent::Exclusive),
+        };
+
+        let mut guard = TreeGuard {
+            holds: Vec::with_capacity(nodes.len()),
+        };
+
+        for (i, node) in nodes.iter().enumerate() {
+            let intent = if i + 1 == nodes.len() { target } else { intention };
+            let dir = self.lock_dir.join(node_name(node));
+            std::fs::create_dir_all(&dir)?;
+
+            loop {
+                if let Some(hold) = register(&dir, intent)? {
+                    guard.holds.push(hold);
+                    break;
+                }
+
+                match backoff.next_delay() {
+                    Some(delay) => std::thread::sleep(delay),
+                    None => return Err(sys::timed_out(path)),
+                }
+            }
+        }
+
+        Ok(guard)
+    }
+}
+
+/// Holds a node of a `LockTree` and the intention locks on its ancestors.
+pub struct TreeGuard {
+    holds: Vec<Hold>,
+}
+
+struct Hold {
+    path: PathBuf,
+    _file: std::fs::File,
+}
+
+impl Drop for TreeGuard {
+    fn drop(&mut self) {
+        // Unlink before unlocking so nobody mistakes the file for a dead holder.
+        while let Some(hold) = self.holds.pop() {
+            let _ = std::fs::remove_file(&hold.path);
+        }
+    }
+}
+
+/// Registers a holder of `intent` on the node unless a conflicting holder exists.
+fn register(dir: &Path, intent: Intent) -> std::io::Result<Option<Hold>> {
+    let _mutex = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .open_exclusive(dir.join("mutex"))?;
+
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let held = match name.to_str().and_then(|name| name.split('-').next()).and_then(Intent::from_tag) {
+            Some(held) => held,
+            None => continue,
+        };
+
+        if intent.compatible(held) {
+            continue;
+        }
+
+        match OpenOptions::new().read(true).try_open_exclusive(entry.path()) {
+            // A holder that is letting go unlinks its file without the mutex.
+            Ok(Some(_)) => match std::fs::remove_file(entry.path()) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {},
+            },
+            Ok(None) => return Ok(None),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e),
+        }
+    }
+
+    // The file is new, so the lock never blocks; taking it without waiting also
+    // keeps the mutex -> holder order out of the lockdep graph.
+    let path = dir.join(format!("{}-{}", intent.tag(), sys::unique_id()));
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .try_open_exclusive(&path)?
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::WouldBlock))?;
+
+    Ok(Some(Hold { path, _file: file }))
+}
+
+/// Returns the root and every node down to `path`.
+fn ancestors(path: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut nodes = vec![PathBuf::new()];
+    let mut current = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => {
+                current.push(name);
+                nodes.push(current.clone());
+            },
+            Component::CurDir => {},
+            _ => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("lock tree paths must be relative and normalized: {}", path.display())));
+            },
+        }
+    }
+
+    Ok(nodes)
+}
+
+/// Flattens a node path into one directory name; the paths of deep nodes are
+/// hashed rather than exceeding NAME_MAX.
+fn node_name(node: &Path) -> String {
+    if node.as_os_str().is_empty() {
+        return "%root".to_owned();
+    }
+
+    sys::file_name(&node.to_string_lossy())
+}
@@ -0,0 +1,94 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use super::{AtomicWriter, OpenOptions};
+use super::atomic::lock_path;
+
+const MAGIC: &[u8; 4] = b"LFV1";
+const HEADER_LEN: usize = MAGIC.len() + 8;
+
+/// A file whose content carries a generation number, for optimistic concurrency.
+///
+/// Readers take a `snapshot` under a short shared lock and can work on it for as long
+/// as they like. A writer then calls `compare_and_swap` with the generation it read,
+/// which only succeeds if nobody else has written in the meantime. Locks are only
+/// held while the file is read or replaced.
+///
+/// The file starts with the magic `LFV1` and the generation as a little-endian u64.
+/// It is replaced with `AtomicWriter`, so its lock is taken on the sidecar
+/// `<name>.lock`. A file that does not exist yet has generation 0 and no content.
+pub struct VersionedFile {
+    path: PathBuf,
+}
+
+#[derive(Debug,Clone,PartialEq)]
+pub struct Snapshot {
+    pub generation: u64,
+    pub content: Vec<u8>,
+}
+
+impl VersionedFile {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn snapshot(&self) -> std::io::Result<Snapshot> {
+        let _lock = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open_shared(lock_path(&self.path))?;
+
+        read(&self.path)
+    }
+
+    pub fn generation(&self) -> std::io::Result<u64> {
+        self.snapshot().map(|snapshot| snapshot.generation)
+    }
+
+    /// Replaces the content if the generation is still `expected`.
+    ///
+    /// Returns the new generation, or `None` if the file was changed since.
+    pub fn compare_and_swap<C: AsRef<[u8]>>(&self, expected: u64, content: C) -> std::io::Result<Option<u64>> {
+        let mut writer = AtomicWriter::new(&self.path)?;
+        if read(&self.path)?.generation != expected {
+            return Ok(None);
+        }
+
+        let generation = expected + 1;
+        writer.write_all(MAGIC)?;
+        writer.write_all(&generation.to_le_bytes())?;
+        writer.write_all(content.as_ref())?;
+        writer.commit()?;
+
+        Ok(Some(generation))
+    }
+}
+
+fn read(path: &Path) -> std::io::Result<Snapshot> {
+    let buf = match std::fs::read(path) {
+        Ok(buf) => buf,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(Snapshot { generation: 0, content: Vec::new() });
+        },
+        Err(e) => return Err(e),
+    };
+
+    if buf.len() < HEADER_LEN || &buf[..MAGIC.len()] != MAGIC {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("not a versioned file: {}", path.display())));
+    }
+
+    let mut generation = [0u8; 8];
+    generation.copy_from_slice(&buf[MAGIC.len()..HEADER_LEN]);
+
+    Ok(Snapshot {
+        generation: u64::from_le_bytes(generation),
+        content: buf[HEADER_LEN..].to_vec(),
+    })
+}
@@ -0,0 +1,239 @@
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::LockMode;
+
+pub(crate) fn fcntl_lock(fd: RawFd, cmd: libc::c_int, ty: libc::c_int, start: u64, len: u64) -> std::io::Result<()> {
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = ty as _;
+    lock.l_whence = libc::SEEK_SET as _;
+    lock.l_start = start as _;
+    lock.l_len = len as _;
+
+    if unsafe { libc::fcntl(fd, cmd, &lock) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Locks the whole file with `flock(2)`. Returns false if `wait` is false and the lock is held.
+pub(crate) fn flock<F: AsRawFd>(file: &F, mode: LockMode, wait: bool) -> std::io::Result<bool> {
+    let mut operation = match mode {
+        LockMode::Shared => libc::LOCK_SH,
+        LockMode::Exclusive => libc::LOCK_EX,
+    };
+    if !wait {
+        operation |= libc::LOCK_NB;
+    }
+
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), operation) } == 0 {
+            return Ok(true);
+        }
+
+        let e = std::io::Error::last_os_error();
+        match e.kind() {
+            std::io::ErrorKind::Interrupted => continue,
+            _ if is_would_block(&e) => return Ok(false),
+            _ => return Err(e),
+        }
+    }
+}
+
+/// Locks the whole file with an open file description lock. Returns false if `wait`
+/// is false and the lock is held.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn ofd_lock<F: AsRawFd>(file: &F, mode: LockMode, wait: bool) -> std::io::Result<bool> {
+    let ty = match mode {
+        LockMode::Shared => libc::F_RDLCK,
+        LockMode::Exclusive => libc::F_WRLCK,
+    };
+    let cmd = if wait { libc::F_OFD_SETLKW } else { libc::F_OFD_SETLK };
+
+    loop {
+        match fcntl_lock(file.as_raw_fd(), cmd, ty, 0, 0) {
+            Ok(()) => return Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) if is_would_block(&e) || e.raw_os_error() == Some(libc::EACCES) => return Ok(false),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Waits for a record lock on a byte range, or releases it with `libc::F_UNLCK`.
+///
+/// Uses open file description locks, so that the lock belongs to `file` like a
+/// `flock` lock does, rather than to the whole process.
+#[cfg(all(feature = "memmap", any(target_os = "linux", target_os = "android")))]
+pub(crate) fn range_lock<F: AsRawFd>(file: &F, ty: libc::c_int, start: u64, len: u64) -> std::io::Result<()> {
+    loop {
+        match fcntl_lock(file.as_raw_fd(), libc::F_OFD_SETLKW, ty, start, len) {
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            result => return result,
+        }
+    }
+}
+
+/// Classic POSIX record locks are no substitute: they belong to the whole process,
+/// so they do not exclude threads or files opened separately in one process, and
+/// closing any descriptor of the file releases all of them.
+#[cfg(all(feature = "memmap", not(any(target_os = "linux", target_os = "android"))))]
+pub(crate) fn range_lock<F: AsRawFd>(_file: &F, _ty: libc::c_int, _start: u64, _len: u64) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "byte-range locks need open file description locks, which this system does not have"))
+}
+
+/// Whether `file` is still linked at `path`, so that a lock taken on it after opening
+/// `path` did not land on a file that was removed or replaced in the meantime.
+pub(crate) fn is_linked_at(file: &std::fs::File, path: &Path) -> std::io::Result<bool> {
+    let meta = file.metadata()?;
+    match std::fs::metadata(path) {
+        Ok(current) => Ok((current.dev(), current.ino()) == (meta.dev(), meta.ino())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+pub(crate) fn is_would_block(e: &std::io::Error) -> bool {
+    match e.raw_os_error() {
+        Some(code) => code == libc::EWOULDBLOCK || code == libc::EAGAIN,
+        None => false,
+    }
+}
+
+pub(crate) fn clear_nonblock<F: AsRawFd>(file: &F) -> std::io::Result<()> {
+    let fd = file.as_raw_fd();
+    let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+    if flags < 0 || unsafe { libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+pub(crate) fn timed_out(path: &Path) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::TimedOut,
+        format!("timed out waiting for the lock on {}", path.display()))
+}
+
+/// Polling intervals for lock attempts with a deadline, doubling from 1ms up to 100ms.
+pub(crate) struct Backoff {
+    deadline: Option<Instant>,
+    delay: Duration,
+}
+
+impl Backoff {
+    pub(crate) fn new(timeout: Duration) -> Self {
+        Self {
+            deadline: Some(Instant::now() + timeout),
+            delay: Duration::from_millis(1),
+        }
+    }
+
+    pub(crate) fn forever() -> Self {
+        Self {
+            deadline: None,
+            delay: Duration::from_millis(1),
+        }
+    }
+
+    /// Returns None once the deadline has passed.
+    pub(crate) fn next_delay(&mut self) -> Option<Duration> {
+        let mut delay = self.delay;
+        if let Some(deadline) = self.deadline {
+            let remaining = deadline.checked_duration_since(Instant::now())?;
+            if remaining.is_zero() {
+                return None;
+            }
+            delay = std::cmp::min(delay, remaining);
+        }
+
+        self.delay = std::cmp::min(self.delay * 2, Duration::from_millis(100));
+
+        Some(delay)
+    }
+}
+
+/// The longest file name that leaves room for sidecars such as `.lock` and `.tmp`
+/// within NAME_MAX.
+const MAX_NAME_LEN: usize = 255 - ".lock".len();
+
+/// A file name for `key`: the percent-encoded key, or for keys whose encoding is
+/// too long, its beginning followed by `~` and a hash of the key. `~` is always
+/// escaped in encoded keys, so the two kinds never collide.
+pub(crate) fn file_name(key: &str) -> String {
+    let name = encode_name(key);
+    if name.len() <= MAX_NAME_LEN {
+        return name;
+    }
+
+    let hash = format!("~{:032x}", fnv1a_128(key.as_bytes()));
+    // Encoded keys are ASCII, so any byte offset is a character boundary. The
+    // beginning is only kept for readability, and may end within an escape.
+    format!("{}{}", &name[..MAX_NAME_LEN - hash.len()], hash)
+}
+
+pub(crate) fn is_hashed_name(name: &str) -> bool {
+    match name.rsplit_once('~') {
+        Some((prefix, hash)) => {
+            hash.len() == 32
+                && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+                && prefix.bytes().all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'%'))
+        },
+        None => false,
+    }
+}
+
+/// FNV-1a with 128 bits, which unlike `std::hash` is the same in every process and version.
+fn fnv1a_128(bytes: &[u8]) -> u128 {
+    const OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+    const PRIME: u128 = 0x0000000001000000000000000000013b;
+
+    bytes.iter().fold(OFFSET_BASIS, |hash, &byte| (hash ^ byte as u128).wrapping_mul(PRIME))
+}
+
+fn encode_name(key: &str) -> String {
+    let mut name = String::with_capacity(key.len());
+    for byte in key.bytes() {
+        if byte.is_ascii_alphanumeric() || byte == b'-' || byte == b'_' {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+
+    name
+}
+
+/// An identifier that is unique across hosts, processes and calls.
+pub(crate) fn unique_id() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+
+    format!("{}:{}:{}:{}",
+        hostname(),
+        std::process::id(),
+        nanos,
+        COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let ret = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) };
+    if ret != 0 {
+        return "localhost".to_owned();
+    }
+
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
@@ -1,4 +1,22 @@
 use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use crate::{LockMode, SyncPolicy};
+use crate::probe::Backend;
+use crate::sys;
+
+mod election;
+mod guard;
+mod kv;
+mod lease;
+mod lockset;
+pub use election::{Campaign, LeaderElection, Leadership};
+pub use guard::LockedFile;
+pub use kv::KvStore;
+pub use lease::Lease;
+pub use lockset::{LockSet, LockSetGuard};
+pub use crate::election::ElectionEvent;
+pub use crate::lease::LeaseRecord;
 
 pub struct SharedFile;
 
@@ -9,6 +27,15 @@ impl SharedFile {
             .open_shared(path)
             .await
     }
+
+    /// Reads content written by `OwnedFile::write_framed`, failing with an error that
+    /// wraps `Corrupted` if its length or checksum does not match.
+    pub async fn read_framed<P: AsRef<Path>>(path: P) -> std::io::Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        Self::open(path).await?.read_to_end(&mut buf).await?;
+
+        crate::frame::decode(buf)
+    }
 }
 
 pub struct OwnedFile;
@@ -28,12 +55,24 @@ impl OwnedFile {
             .open_exclusive(path)
             .await
     }
+
+    /// Replaces the content of the file with `content`, framed with its length and CRC32C.
+    pub async fn write_framed<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, content: C) -> std::io::Result<()> {
+        let mut file = Self::create(path).await?;
+        file.set_len(0).await?;
+        file.write_all(&crate::frame::encode(content.as_ref())).await?;
+
+        file.flush().await
+    }
 }
 
 pub struct OpenOptions {
     sys: tokio::fs::OpenOptions,
     #[cfg(unix)]
     flags: i32,
+    unreliable: crate::UnreliableLock,
+    sync: SyncPolicy,
+    sync_parent: bool,
 }
 
 impl OpenOptions {
@@ -42,23 +81,140 @@ impl OpenOptions {
             sys: tokio::fs::OpenOptions::new(),
             #[cfg(unix)]
             flags: 0,
+            unreliable: crate::UnreliableLock::default(),
+            sync: SyncPolicy::default(),
+            sync_parent: false,
         }
     }
 
     crate::impl_open_options!();
 
+    /// Flushes exclusively locked files to disk before their lock is released.
+    ///
+    /// This only applies to `LockedFile` guards returned by `lock` and `try_lock`;
+    /// a bare `File` releases its lock when closed, without a chance to sync.
+    pub fn sync_on_unlock(&mut self, policy: SyncPolicy) -> &mut Self {
+        self.sync = policy;
+
+        self
+    }
+
+    /// Also syncs the parent directory, so that a newly created file survives a crash.
+    pub fn sync_parent_on_unlock(&mut self, sync_parent: bool) -> &mut Self {
+        self.sync_parent = sync_parent;
+
+        self
+    }
+
+    pub async fn lock<P: AsRef<Path>>(&self, path: P, mode: LockMode) -> std::io::Result<LockedFile> {
+        let file = self.open_with(path.as_ref(), mode).await?;
+
+        Ok(self.guard(file, path.as_ref(), mode))
+    }
+
+    pub async fn try_lock<P: AsRef<Path>>(&self, path: P, mode: LockMode) -> std::io::Result<Option<LockedFile>> {
+        let file = self.try_open_with(path.as_ref(), mode).await?;
+
+        Ok(file.map(|file| self.guard(file, path.as_ref(), mode)))
+    }
+
+    fn guard(&self, file: tokio::fs::File, path: &Path, mode: LockMode) -> LockedFile {
+        LockedFile::new(file, path.to_path_buf(), mode, self.sync, self.sync_parent)
+    }
+
     pub async fn open_exclusive<P: AsRef<Path>>(&self, path: P) -> std::io::Result<tokio::fs::File> {
-        self.sys.clone()
-            .custom_flags(self.flags | libc::O_EXLOCK)
-            .open(path)
-            .await
+        self.open_with(path, LockMode::Exclusive).await
     }
 
     pub async fn open_shared<P: AsRef<Path>>(&self, path: P) -> std::io::Result<tokio::fs::File> {
-        self.sys.clone()
-            .custom_flags(self.flags | libc::O_SHLOCK)
+        self.open_with(path, LockMode::Shared).await
+    }
+
+    pub async fn open_with<P: AsRef<Path>>(&self, path: P, mode: LockMode) -> std::io::Result<tokio::fs::File> {
+        self.open_locked(path.as_ref(), mode, true).await?
+            .ok_or_else(|| std::io::ErrorKind::WouldBlock.into())
+    }
+
+    /// Returns `Ok(None)` instead of waiting if the file is locked by someone else.
+    pub async fn try_open_exclusive<P: AsRef<Path>>(&self, path: P) -> std::io::Result<Option<tokio::fs::File>> {
+        self.try_open_with(path, LockMode::Exclusive).await
+    }
+
+    /// Returns `Ok(None)` instead of waiting if the file is locked exclusively by someone else.
+    pub async fn try_open_shared<P: AsRef<Path>>(&self, path: P) -> std::io::Result<Option<tokio::fs::File>> {
+        self.try_open_with(path, LockMode::Shared).await
+    }
+
+    pub async fn try_open_with<P: AsRef<Path>>(&self, path: P, mode: LockMode) -> std::io::Result<Option<tokio::fs::File>> {
+        self.open_locked(path.as_ref(), mode, false).await
+    }
+
+    /// Fails with `ErrorKind::TimedOut` if the lock cannot be taken within `timeout`.
+    pub async fn open_timeout<P: AsRef<Path>>(&self, path: P, mode: LockMode, timeout: Duration) -> std::io::Result<tokio::fs::File> {
+        let path = path.as_ref();
+        let mut backoff = sys::Backoff::new(timeout);
+
+        loop {
+            if let Some(file) = self.open_locked(path, mode, false).await? {
+                return Ok(file);
+            }
+
+            match backoff.next_delay() {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return Err(sys::timed_out(path)),
+            }
+        }
+    }
+
+    async fn open_locked(&self, path: &Path, mode: LockMode, wait: bool) -> std::io::Result<Option<tokio::fs::File>> {
+        let backend = match self.unreliable {
+            crate::UnreliableLock::Ignore => Backend::Flock,
+            policy => {
+                let path = path.to_path_buf();
+                spawn_blocking(move || crate::probe::select_backend(&path, policy)).await?
+            },
+        };
+
+        match (backend, mode.open_flag()) {
+            (Backend::Flock, Some(flag)) => {
+                let nonblock = if wait { 0 } else { libc::O_NONBLOCK };
+                let result = self.sys.clone()
+                    .custom_flags(self.flags | flag | nonblock)
+                    .open(path)
+                    .await;
+
+                match result {
+                    Ok(file) => {
+                        if !wait && self.flags & libc::O_NONBLOCK == 0 {
+                            sys::clear_nonblock(&file)?;
+                        }
+                        Ok(Some(file))
+                    },
+                    Err(e) if !wait && sys::is_would_block(&e) => Ok(None),
+                    Err(e) => Err(e),
+                }
+            },
+            (Backend::Flock, None) => self.open_then_lock(path, move |file| sys::flock(file, mode, wait)).await,
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            (Backend::Ofd, _) => self.open_then_lock(path, move |file| sys::ofd_lock(file, mode, wait)).await,
+        }
+    }
+
+    async fn open_then_lock<F>(&self, path: &Path, lock: F) -> std::io::Result<Option<tokio::fs::File>>
+    where
+        F: FnOnce(&std::fs::File) -> std::io::Result<bool> + Send + 'static,
+    {
+        let file = self.sys.clone()
+            .custom_flags(self.flags)
             .open(path)
-            .await
+            .await?
+            .into_std()
+            .await;
+        let locked = spawn_blocking(move || {
+            lock(&file).map(|locked| locked.then_some(file))
+        }).await?;
+
+        Ok(locked.map(tokio::fs::File::from_std))
     }
 
     pub fn mode(&mut self, mode: u32) -> &mut Self {
@@ -73,3 +229,13 @@ impl OpenOptions {
         self
     }
 }
+
+async fn spawn_blocking<F, T>(f: F) -> std::io::Result<T>
+where
+    F: FnOnce() -> std::io::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|e| Err(std::io::Error::other(e)))
+}
@@ -0,0 +1,201 @@
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::task::Poll;
+use std::time::Duration;
+use tokio::sync::{mpsc, Notify};
+use crate::LockMode;
+use crate::election::{parse_identity, same_file, write_identity, ElectionEvent, WAIT_SLICE};
+use super::OpenOptions;
+
+/// Elects one leader among the processes that hold a well-known file.
+///
+/// This is the tokio counterpart of `lockedfile::std::LeaderElection`; campaigns
+/// run in a spawned task instead of a thread.
+pub struct LeaderElection {
+    path: PathBuf,
+    identity: String,
+}
+
+impl LeaderElection {
+    pub fn new<P: AsRef<Path>, S: Into<String>>(path: P, identity: S) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            identity: identity.into(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn identity(&self) -> &str {
+        &self.identity
+    }
+
+    /// The identity of the current leader, or `None` if nobody holds the election file.
+    pub async fn leader<P: AsRef<Path>>(path: P) -> std::io::Result<Option<String>> {
+        match OpenOptions::new().read(true).try_open_shared(path.as_ref()).await {
+            Ok(Some(_)) => return Ok(None),
+            Ok(None) => {},
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        match tokio::fs::read(path).await {
+            Ok(buf) => Ok(parse_identity(&buf)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Waits until this candidate becomes the leader.
+    pub async fn lead(&self) -> std::io::Result<Leadership> {
+        let file = self.options().open_exclusive(&self.path).await?;
+
+        self.take_office(file).await
+    }
+
+    /// Returns `Ok(None)` if another candidate is the leader.
+    pub async fn try_lead(&self) -> std::io::Result<Option<Leadership>> {
+        match self.options().try_open_exclusive(&self.path).await? {
+            Some(file) => self.take_office(file).await.map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Waits for leadership in a spawned task, which reports when it is gained or
+    /// lost.
+    ///
+    /// The task waits on the lock for 100ms at a time, so that stopping the campaign
+    /// never leaves a blocking thread behind. While leading, it checks every
+    /// `interval` that the election file was not replaced.
+    pub fn campaign(&self, interval: Duration) -> Campaign {
+        let election = Self::new(&self.path, self.identity.clone());
+        let (events, receiver) = mpsc::unbounded_channel();
+        let stop = Arc::new(Notify::new());
+        let leading = Arc::new(Mutex::new(false));
+
+        {
+            let stop = stop.clone();
+            let leading = leading.clone();
+            tokio::spawn(async move {
+                loop {
+                    let lead = async {
+                        loop {
+                            match election.options().open_timeout(&election.path, LockMode::Exclusive, WAIT_SLICE).await {
+                                Err(e) if e.kind() == std::io::ErrorKind::TimedOut => {},
+                                Ok(file) => return election.take_office(file).await,
+                                Err(e) => return Err(e),
+                            }
+                        }
+                    };
+                    let stopped = stop.notified();
+                    tokio::pin!(lead, stopped);
+                    // A lock taken by the abandoned attempt is released when it is dropped.
+                    let leadership = std::future::poll_fn(|cx| match stopped.as_mut().poll(cx) {
+                        Poll::Ready(()) => Poll::Ready(None),
+                        Poll::Pending => lead.as_mut().poll(cx).map(Some),
+                    }).await;
+
+                    let leadership = match leadership {
+                        None => return,
+                        Some(Ok(leadership)) => leadership,
+                        Some(Err(_)) if tokio::time::timeout(interval, stop.notified()).await.is_ok() => return,
+                        Some(Err(_)) => continue,
+                    };
+                    *leading.lock().unwrap() = true;
+                    let _ = events.send(ElectionEvent::Gained);
+
+                    loop {
+                        if tokio::time::timeout(interval, stop.notified()).await.is_ok() {
+                            return;
+                        }
+                        if let Ok(false) = leadership.is_valid().await {
+                            break;
+                        }
+                    }
+                    *leading.lock().unwrap() = false;
+                    let _ = events.send(ElectionEvent::Lost);
+                }
+            });
+        }
+
+        Campaign {
+            events: receiver,
+            leading,
+            stop,
+        }
+    }
+
+    fn options(&self) -> OpenOptions {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true);
+
+        options
+    }
+
+    async fn take_office(&self, file: tokio::fs::File) -> std::io::Result<Leadership> {
+        let file = file.into_std().await;
+        let identity = self.identity.clone();
+        let file = super::spawn_blocking(move || write_identity(&file, &identity).map(|()| file)).await?;
+
+        Ok(Leadership {
+            path: self.path.clone(),
+            file,
+        })
+    }
+}
+
+/// Held by the leader; dropping it steps down.
+pub struct Leadership {
+    path: PathBuf,
+    file: std::fs::File,
+}
+
+impl Leadership {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns false if the election file was removed or replaced, so that another
+    /// candidate may have become the leader through the new file.
+    pub async fn is_valid(&self) -> std::io::Result<bool> {
+        match tokio::fs::metadata(&self.path).await {
+            Ok(meta) => Ok(same_file(&meta, &self.file.metadata()?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for Leadership {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+    }
+}
+
+/// A background campaign started by `LeaderElection::campaign`; dropping it stops
+/// the campaign and, shortly after, steps down.
+pub struct Campaign {
+    events: mpsc::UnboundedReceiver<ElectionEvent>,
+    leading: Arc<Mutex<bool>>,
+    stop: Arc<Notify>,
+}
+
+impl Campaign {
+    pub fn is_leader(&self) -> bool {
+        *self.leading.lock().unwrap()
+    }
+
+    /// Waits for the next event.
+    pub async fn recv(&mut self) -> Option<ElectionEvent> {
+        self.events.recv().await
+    }
+}
+
+impl Drop for Campaign {
+    fn drop(&mut self) {
+        self.stop.notify_one();
+    }
+}
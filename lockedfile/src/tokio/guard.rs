@@ -0,0 +1,145 @@
+use std::io::SeekFrom;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+use crate::{LockMode, SyncPolicy};
+
+/// A file together with the lock taken on it, returned by `OpenOptions::lock`.
+///
+/// This is the tokio counterpart of `lockedfile::std::LockedFile`. Syncing blocks, so
+/// it happens in `unlock().await`; a guard that is dropped instead is synced and
+/// released by a task spawned on the current runtime.
+#[derive(Debug)]
+pub struct LockedFile {
+    file: Option<tokio::fs::File>,
+    path: PathBuf,
+    mode: LockMode,
+    sync: SyncPolicy,
+    sync_parent: bool,
+}
+
+impl LockedFile {
+    pub(crate) fn new(file: tokio::fs::File, path: PathBuf, mode: LockMode, sync: SyncPolicy, sync_parent: bool) -> Self {
+        Self {
+            file: Some(file),
+            path,
+            mode,
+            sync,
+            sync_parent,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn mode(&self) -> LockMode {
+        self.mode
+    }
+
+    /// Applies the sync policy and releases the lock, reporting sync errors that dropping would ignore.
+    pub async fn unlock(mut self) -> std::io::Result<()> {
+        match self.file.take() {
+            Some(file) if self.mode == LockMode::Exclusive => sync(&file, &self.path, self.sync, self.sync_parent).await,
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the file without syncing it; the lock is released when the file is closed.
+    pub fn into_inner(mut self) -> tokio::fs::File {
+        self.file.take().unwrap()
+    }
+}
+
+impl Drop for LockedFile {
+    fn drop(&mut self) {
+        if let Some(file) = self.file.take() {
+            if self.mode == LockMode::Exclusive {
+                sync_in_background(file, self.path.clone(), self.sync, self.sync_parent);
+            }
+        }
+    }
+}
+
+impl Deref for LockedFile {
+    type Target = tokio::fs::File;
+
+    fn deref(&self) -> &tokio::fs::File {
+        self.file.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for LockedFile {
+    fn deref_mut(&mut self) -> &mut tokio::fs::File {
+        self.file.as_mut().unwrap()
+    }
+}
+
+impl AsyncRead for LockedFile {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(self.get_mut().deref_mut()).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for LockedFile {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        Pin::new(self.get_mut().deref_mut()).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(self.get_mut().deref_mut()).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(self.get_mut().deref_mut()).poll_shutdown(cx)
+    }
+}
+
+impl AsyncSeek for LockedFile {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        Pin::new(self.get_mut().deref_mut()).start_seek(position)
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Pin::new(self.get_mut().deref_mut()).poll_complete(cx)
+    }
+}
+
+/// Flushes writes still in flight, then applies `policy`.
+pub(crate) async fn sync(file: &tokio::fs::File, path: &Path, policy: SyncPolicy, sync_parent: bool) -> std::io::Result<()> {
+    match policy {
+        SyncPolicy::None => {},
+        SyncPolicy::Data => file.sync_data().await?,
+        SyncPolicy::All => file.sync_all().await?,
+    }
+
+    if sync_parent {
+        let path = path.to_path_buf();
+        super::spawn_blocking(move || crate::std::sync_parent(&path)).await?;
+    }
+
+    Ok(())
+}
+
+/// Syncs and closes `file` from a dropped guard, on the current runtime if there is one.
+pub(crate) fn sync_in_background(file: tokio::fs::File, path: PathBuf, policy: SyncPolicy, sync_parent: bool) {
+    if policy == SyncPolicy::None && !sync_parent {
+        return;
+    }
+
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => {
+            handle.spawn(async move {
+                let _ = sync(&file, &path, policy, sync_parent).await;
+            });
+        },
+        // Outside of a runtime, nothing else can be waiting on this thread.
+        Err(_) => {
+            if let Ok(file) = file.try_into_std() {
+                let _ = crate::std::sync(&file, &path, policy, sync_parent);
+            }
+        },
+    }
+}
@@ -0,0 +1,77 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use super::spawn_blocking;
+
+/// A key-value store in a directory, with one file per key.
+///
+/// This is the tokio counterpart of `lockedfile::std::KvStore`, using the same
+/// format and locks; every operation runs on the blocking thread pool.
+#[derive(Clone)]
+pub struct KvStore {
+    inner: Arc<crate::std::KvStore>,
+}
+
+impl KvStore {
+    pub async fn open<P: AsRef<Path>>(dir: P) -> std::io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let inner = spawn_blocking(move || crate::std::KvStore::open(dir)).await?;
+
+        Ok(Self {
+            inner: Arc::new(inner),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        self.inner.dir()
+    }
+
+    pub async fn get(&self, key: &str) -> std::io::Result<Option<Vec<u8>>> {
+        let (inner, key) = (self.inner.clone(), key.to_owned());
+
+        spawn_blocking(move || inner.get(&key)).await
+    }
+
+    pub async fn put<V: AsRef<[u8]>>(&self, key: &str, value: V) -> std::io::Result<()> {
+        let (inner, key, value) = (self.inner.clone(), key.to_owned(), value.as_ref().to_vec());
+
+        spawn_blocking(move || inner.put(&key, value)).await
+    }
+
+    /// Stores an entry that reads as absent once `ttl` has passed.
+    pub async fn put_with_ttl<V: AsRef<[u8]>>(&self, key: &str, value: V, ttl: Duration) -> std::io::Result<()> {
+        let (inner, key, value) = (self.inner.clone(), key.to_owned(), value.as_ref().to_vec());
+
+        spawn_blocking(move || inner.put_with_ttl(&key, value, ttl)).await
+    }
+
+    /// Returns whether an entry was deleted.
+    pub async fn delete(&self, key: &str) -> std::io::Result<bool> {
+        let (inner, key) = (self.inner.clone(), key.to_owned());
+
+        spawn_blocking(move || inner.delete(&key)).await
+    }
+
+    /// Replaces the value of `key` with `new` if it is still `expected`, where `None`
+    /// stands for an absent entry, and returns whether it did.
+    pub async fn compare_and_swap(&self, key: &str, expected: Option<&[u8]>, new: Option<&[u8]>) -> std::io::Result<bool> {
+        let (inner, key) = (self.inner.clone(), key.to_owned());
+        let (expected, new) = (expected.map(<[u8]>::to_vec), new.map(<[u8]>::to_vec));
+
+        spawn_blocking(move || inner.compare_and_swap(&key, expected.as_deref(), new.as_deref())).await
+    }
+
+    /// The keys of all entries that have not expired, in no particular order.
+    pub async fn keys(&self) -> std::io::Result<Vec<String>> {
+        let inner = self.inner.clone();
+
+        spawn_blocking(move || inner.keys()).await
+    }
+
+    /// Removes expired entries and returns how many there were.
+    pub async fn purge(&self) -> std::io::Result<usize> {
+        let inner = self.inner.clone();
+
+        spawn_blocking(move || inner.purge()).await
+    }
+}
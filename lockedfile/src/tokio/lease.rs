@@ -0,0 +1,209 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+use crate::lease::{self, LeaseRecord};
+use super::{OpenOptions, SharedFile};
+
+/// A lease on a file that stays valid only while its holder keeps refreshing it.
+///
+/// This is the tokio counterpart of `lockedfile::std::Lease`; the timestamp is
+/// refreshed by a spawned task instead of a thread.
+pub struct Lease {
+    path: PathBuf,
+    owner: String,
+    ttl: Duration,
+    state: Arc<Mutex<State>>,
+    stop: Arc<Notify>,
+    refresher: Option<JoinHandle<std::io::Result<()>>>,
+}
+
+struct State {
+    refreshed: Instant,
+    lost: bool,
+}
+
+impl Lease {
+    pub async fn acquire<P: AsRef<Path>>(path: P, ttl: Duration) -> std::io::Result<Self> {
+        loop {
+            if let Some(lease) = Self::try_acquire(path.as_ref(), ttl).await? {
+                return Ok(lease);
+            }
+
+            tokio::time::sleep(lease::poll_interval(ttl)).await;
+        }
+    }
+
+    /// Returns `Ok(None)` if the lease is held by someone else.
+    ///
+    /// The record is read back after a short delay, so that a contender that the lock
+    /// did not keep out, as can happen on network filesystems, is noticed. That only
+    /// narrows the race: two parties are still kept from both taking an expired lease
+    /// by the kernel lock around the read, check and write.
+    pub async fn try_acquire<P: AsRef<Path>>(path: P, ttl: Duration) -> std::io::Result<Option<Self>> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = open_lease_file(&path).await?;
+
+        if let Some(record) = read_record(&mut file).await? {
+            if !record.is_expired(ttl) {
+                return Ok(None);
+            }
+        }
+
+        let acquired = Instant::now();
+        let record = LeaseRecord::new(crate::sys::unique_id());
+        write_record(&mut file, &record).await?;
+        drop(file);
+
+        tokio::time::sleep(lease::confirm_delay(ttl)).await;
+        let mut file = open_lease_file(&path).await?;
+        if read_record(&mut file).await?.is_none_or(|current| current.owner != record.owner) {
+            return Ok(None);
+        }
+        drop(file);
+
+        Ok(Some(Self::start(path, record.owner, ttl, acquired)))
+    }
+
+    /// Reads the current record of the lease file without taking the lease.
+    pub async fn holder<P: AsRef<Path>>(path: P) -> std::io::Result<Option<LeaseRecord>> {
+        match SharedFile::open(path).await {
+            Ok(mut file) => read_record(&mut file).await,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn start(path: PathBuf, owner: String, ttl: Duration, acquired: Instant) -> Self {
+        let state = Arc::new(Mutex::new(State {
+            refreshed: acquired,
+            lost: false,
+        }));
+        let stop = Arc::new(Notify::new());
+
+        let refresher = tokio::spawn(refresh_loop(path.clone(), owner.clone(), ttl, state.clone(), stop.clone()));
+
+        Self {
+            path,
+            owner,
+            ttl,
+            state,
+            stop,
+            refresher: Some(refresher),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn owner(&self) -> &str {
+        &self.owner
+    }
+
+    /// Returns false once the lease was taken over or could not be refreshed in time,
+    /// which is a little before other parties may take it over.
+    pub fn is_held(&self) -> bool {
+        let state = self.state.lock().unwrap();
+
+        !state.lost && state.refreshed.elapsed() < lease::hold_time(self.ttl)
+    }
+
+    pub async fn release(mut self) -> std::io::Result<()> {
+        match self.refresher.take() {
+            Some(refresher) => {
+                self.stop.notify_one();
+                refresher.await
+                    .unwrap_or_else(|e| Err(std::io::Error::other(e)))
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Lease {
+    // The refresher task clears the lease file in the background.
+    fn drop(&mut self) {
+        if self.refresher.take().is_some() {
+            self.stop.notify_one();
+        }
+    }
+}
+
+async fn refresh_loop(
+    path: PathBuf,
+    owner: String,
+    ttl: Duration,
+    state: Arc<Mutex<State>>,
+    stop: Arc<Notify>,
+) -> std::io::Result<()> {
+    let interval = lease::refresh_interval(ttl);
+
+    loop {
+        if tokio::time::timeout(interval, stop.notified()).await.is_ok() {
+            break;
+        }
+
+        match refresh(&path, &owner).await {
+            Ok(Some(refreshed)) => {
+                state.lock().unwrap().refreshed = refreshed;
+            },
+            Ok(None) => {
+                state.lock().unwrap().lost = true;
+                return Ok(());
+            },
+            Err(_) => {},
+        }
+    }
+
+    clear(&path, &owner).await
+}
+
+/// Returns when the refresh started, or `None` if the lease was taken over.
+async fn refresh(path: &Path, owner: &str) -> std::io::Result<Option<Instant>> {
+    let mut file = open_lease_file(path).await?;
+
+    match read_record(&mut file).await? {
+        Some(record) if record.owner == owner => {
+            let refreshed = Instant::now();
+            write_record(&mut file, &LeaseRecord::new(owner.to_owned())).await?;
+            Ok(Some(refreshed))
+        },
+        _ => Ok(None),
+    }
+}
+
+async fn clear(path: &Path, owner: &str) -> std::io::Result<()> {
+    let mut file = open_lease_file(path).await?;
+
+    match read_record(&mut file).await? {
+        Some(record) if record.owner == owner => file.set_len(0).await,
+        _ => Ok(()),
+    }
+}
+
+async fn open_lease_file(path: &Path) -> std::io::Result<tokio::fs::File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open_exclusive(path)
+        .await
+}
+
+async fn read_record(file: &mut tokio::fs::File) -> std::io::Result<Option<LeaseRecord>> {
+    let mut buf = Vec::new();
+    file.seek(std::io::SeekFrom::Start(0)).await?;
+    file.read_to_end(&mut buf).await?;
+
+    Ok(LeaseRecord::from_bytes(&buf))
+}
+
+async fn write_record(file: &mut tokio::fs::File, record: &LeaseRecord) -> std::io::Result<()> {
+    file.set_len(0).await?;
+    file.seek(std::io::SeekFrom::Start(0)).await?;
+    file.write_all(&record.to_bytes()).await?;
+    file.sync_data().await
+}
@@ -0,0 +1,177 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use crate::{LockMode, SyncPolicy};
+use crate::lockset::{self, Entry};
+use super::OpenOptions;
+use super::guard::{sync, sync_in_background};
+
+/// Locks several files at once without deadlocking against other lock sets.
+///
+/// This is the tokio counterpart of `lockedfile::std::LockSet`, and both lock in the same order.
+#[derive(Debug,Clone,Default)]
+pub struct LockSet {
+    requests: Vec<(PathBuf, LockMode)>,
+    create: bool,
+    timeout: Option<Duration>,
+    sync: SyncPolicy,
+}
+
+impl LockSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn shared<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.add(path, LockMode::Shared)
+    }
+
+    pub fn exclusive<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.add(path, LockMode::Exclusive)
+    }
+
+    pub fn add<P: AsRef<Path>>(&mut self, path: P, mode: LockMode) -> &mut Self {
+        self.requests.push((path.as_ref().to_path_buf(), mode));
+
+        self
+    }
+
+    /// Creates missing files before locking them.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+
+        self
+    }
+
+    /// Flushes the exclusively locked files to disk before the guard releases them.
+    pub fn sync_on_unlock(&mut self, policy: SyncPolicy) -> &mut Self {
+        self.sync = policy;
+
+        self
+    }
+
+    /// Bounds the time spent waiting for all locks together.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.timeout = Some(timeout);
+
+        self
+    }
+
+    pub async fn lock(&self) -> std::io::Result<LockSetGuard> {
+        let deadline = self.timeout.map(|timeout| std::time::Instant::now() + timeout);
+
+        loop {
+            if let Some(files) = self.try_plan(deadline).await? {
+                return Ok(LockSetGuard {
+                    files,
+                    sync: self.sync,
+                });
+            }
+        }
+    }
+
+    /// Locks the files in the planned order, or releases them and returns None if a
+    /// path was replaced after planning.
+    async fn try_plan(&self, deadline: Option<std::time::Instant>) -> std::io::Result<Option<Vec<LockedEntry>>> {
+        let (requests, create) = (self.requests.clone(), self.create);
+        let entries = super::spawn_blocking(move || lockset::plan(&requests, create)).await?;
+        let mut files = Vec::with_capacity(entries.len());
+
+        for Entry { key, paths, mode } in entries {
+            let options = entry_options(mode);
+            let file = match deadline {
+                Some(deadline) => {
+                    let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                    options.open_timeout(&paths[0], mode, remaining).await?
+                },
+                None => options.open_with(&paths[0], mode).await?,
+            };
+            let meta = file.metadata().await?;
+            let (current, paths) = super::spawn_blocking(move || {
+                lockset::is_current(key, &paths, &meta).map(|current| (current, paths))
+            }).await?;
+            if !current {
+                return Ok(None);
+            }
+
+            files.push(LockedEntry { paths, mode, file });
+        }
+
+        Ok(Some(files))
+    }
+}
+
+fn entry_options(mode: LockMode) -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.read(true);
+    if mode == LockMode::Exclusive {
+        options.write(true);
+    }
+
+    options
+}
+
+struct LockedEntry {
+    paths: Vec<PathBuf>,
+    mode: LockMode,
+    file: tokio::fs::File,
+}
+
+/// Holds every lock of a `LockSet`; all of them are released when it is dropped.
+///
+/// Exclusively locked files are opened for reading and writing, shared ones for reading.
+/// As with `LockedFile`, the sync policy is applied in `unlock().await`, or by a task
+/// spawned on the current runtime when the guard is dropped.
+pub struct LockSetGuard {
+    files: Vec<LockedEntry>,
+    sync: SyncPolicy,
+}
+
+impl LockSetGuard {
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&tokio::fs::File> {
+        self.find(path.as_ref()).map(|i| &self.files[i].file)
+    }
+
+    pub fn get_mut<P: AsRef<Path>>(&mut self, path: P) -> Option<&mut tokio::fs::File> {
+        self.find(path.as_ref()).map(move |i| &mut self.files[i].file)
+    }
+
+    pub fn mode<P: AsRef<Path>>(&self, path: P) -> Option<LockMode> {
+        self.find(path.as_ref()).map(|i| self.files[i].mode)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Applies the sync policy and releases every lock, reporting the first sync error.
+    pub async fn unlock(mut self) -> std::io::Result<()> {
+        let mut result = Ok(());
+
+        for entry in self.files.drain(..) {
+            if entry.mode == LockMode::Exclusive {
+                let synced = sync(&entry.file, &entry.paths[0], self.sync, false).await;
+                result = result.and(synced);
+            }
+        }
+
+        result
+    }
+
+    fn find(&self, path: &Path) -> Option<usize> {
+        self.files.iter().position(|entry| entry.paths.iter().any(|p| p == path))
+    }
+}
+
+impl Drop for LockSetGuard {
+    fn drop(&mut self) {
+        for entry in self.files.drain(..) {
+            if entry.mode == LockMode::Exclusive {
+                sync_in_background(entry.file, entry.paths[0].clone(), self.sync, false);
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use std::io::Write;
+use lockedfile::Corrupted;
+use lockedfile::std::AppendLog;
+
+fn collect(log: &AppendLog) -> Vec<Vec<u8>> {
+    log.records().unwrap().map(Result::unwrap).collect()
+}
+
+#[test]
+fn concurrent_appends_do_not_interleave() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log");
+    AppendLog::open(&path).unwrap();
+
+    // Larger than PIPE_BUF, so a single write is not atomic by itself.
+    let threads = (0..4u8).map(|i| {
+        let path = path.clone();
+        std::thread::spawn(move || {
+            let log = AppendLog::open(&path).unwrap();
+            for _ in 0..20 {
+                log.append(vec![i; 64 * 1024]).unwrap();
+            }
+        })
+    }).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let records = collect(&AppendLog::open(&path).unwrap());
+    assert_eq!(records.len(), 80);
+    for record in records {
+        assert_eq!(record.len(), 64 * 1024);
+        assert!(record.iter().all(|&b| b == record[0]));
+    }
+}
+
+#[test]
+fn truncated_tail_is_skipped_and_repaired() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log");
+    let log = AppendLog::open(&path).unwrap();
+    log.append("first").unwrap();
+    log.append("second").unwrap();
+
+    let len = std::fs::metadata(&path).unwrap().len();
+    std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 2).unwrap();
+    let records = log.records_unlocked().unwrap().map(Result::unwrap).collect::<Vec<_>>();
+    assert_eq!(records, [b"first".to_vec()]);
+
+    let log = AppendLog::open(&path).unwrap();
+    log.append("third").unwrap();
+    assert_eq!(collect(&log), [b"first".to_vec(), b"third".to_vec()]);
+}
+
+#[test]
+fn corrupted_record_is_reported_and_truncated() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log");
+    let log = AppendLog::open(&path).unwrap();
+    log.append("first").unwrap();
+
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[5, 0, 0, 0, 1, 2, 3, 4]).unwrap();
+    file.write_all(b"bogus").unwrap();
+    drop(file);
+
+    let mut records = log.records().unwrap();
+    assert_eq!(records.next().unwrap().unwrap(), b"first");
+    let err = records.next().unwrap().unwrap_err();
+    assert!(err.into_inner().unwrap().is::<Corrupted>());
+    assert!(records.next().is_none());
+    drop(records);
+
+    let reopened = AppendLog::open(&path).unwrap();
+    assert_eq!(collect(&reopened), [b"first".to_vec()]);
+}
+
+#[test]
+fn append_repairs_a_tail_torn_by_another_writer() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log");
+    let log = AppendLog::open(&path).unwrap();
+    log.append("first").unwrap();
+
+    // Another writer appends a record, then dies halfway through the next one.
+    AppendLog::open(&path).unwrap().append("second").unwrap();
+    let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[100, 0, 0, 0, 1, 2, 3, 4, b'x']).unwrap();
+    drop(file);
+
+    log.append("third").unwrap();
+    assert_eq!(collect(&log), [b"first".to_vec(), b"second".to_vec(), b"third".to_vec()]);
+}
+
+#[test]
+fn corrupted_record_in_the_middle_is_not_truncated() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("log");
+    let log = AppendLog::open(&path).unwrap();
+    log.append("first").unwrap();
+    log.append("second").unwrap();
+    log.append("third").unwrap();
+
+    // Flips a byte of "second", behind the 8-byte headers of both records.
+    let mut contents = std::fs::read(&path).unwrap();
+    contents[8 + 5 + 8] ^= 0xff;
+    std::fs::write(&path, &contents).unwrap();
+
+    let err = AppendLog::open(&path).err().unwrap();
+    assert!(err.into_inner().unwrap().is::<Corrupted>());
+    assert_eq!(std::fs::read(&path).unwrap(), contents);
+
+    let mut records = log.records().unwrap();
+    assert_eq!(records.next().unwrap().unwrap(), b"first");
+    assert!(records.next().unwrap().is_err());
+}
@@ -0,0 +1,95 @@
+use std::io::{Read, Write};
+use lockedfile::std::{atomic_write, AtomicWriter, SharedFile};
+
+fn read_shared(path: &std::path::Path) -> String {
+    let mut buf = String::new();
+    SharedFile::open(path).unwrap().read_to_string(&mut buf).unwrap();
+    buf
+}
+
+fn entries(dir: &std::path::Path) -> Vec<String> {
+    let mut names = std::fs::read_dir(dir).unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect::<Vec<_>>();
+    names.sort();
+    names
+}
+
+#[test]
+fn atomic_write_replaces_content() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.json");
+
+    atomic_write(&path, "first").unwrap();
+    assert_eq!(read_shared(&path), "first");
+
+    atomic_write(&path, "second").unwrap();
+    assert_eq!(read_shared(&path), "second");
+    assert_eq!(entries(dir.path()), ["config.json", "config.json.lock"]);
+}
+
+#[test]
+fn uncommitted_writer_leaves_target_untouched() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("config.json");
+    atomic_write(&path, "original").unwrap();
+
+    {
+        let mut writer = AtomicWriter::new(&path).unwrap();
+        writer.write_all(b"partial").unwrap();
+        assert_eq!(read_shared(&path), "original");
+    }
+
+    assert_eq!(read_shared(&path), "original");
+    assert_eq!(entries(dir.path()), ["config.json", "config.json.lock"]);
+}
+
+#[test]
+fn atomic_write_keeps_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("secret");
+    std::fs::write(&path, "old").unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+
+    atomic_write(&path, "new").unwrap();
+
+    let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+#[test]
+fn anonymous_writer_publishes_on_commit() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("index");
+
+    for content in ["created", "replaced"].iter() {
+        let mut writer = AtomicWriter::anonymous(&path).unwrap();
+        writer.write_all(content.as_bytes()).unwrap();
+        assert_eq!(entries(dir.path()).iter().filter(|name| name.ends_with(".tmp")).count(), 0);
+        writer.commit().unwrap();
+
+        assert_eq!(read_shared(&path), *content);
+    }
+
+    drop(AtomicWriter::anonymous(&path).unwrap());
+    assert_eq!(entries(dir.path()), ["index", "index.lock"]);
+}
+
+#[test]
+fn next_writer_removes_a_temp_file_left_by_a_crash() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("index");
+    std::fs::write(dir.path().join("index.tmp"), "torn").unwrap();
+
+    let mut writer = AtomicWriter::anonymous(&path).unwrap();
+    writer.write_all(b"anonymous").unwrap();
+    writer.commit().unwrap();
+    assert_eq!(entries(dir.path()), ["index", "index.lock"]);
+
+    std::fs::write(dir.path().join("index.tmp"), "torn").unwrap();
+    atomic_write(&path, "named").unwrap();
+    assert_eq!(read_shared(&path), "named");
+    assert_eq!(entries(dir.path()), ["index", "index.lock"]);
+}
@@ -0,0 +1,69 @@
+use lockedfile::std::CacheDir;
+
+#[test]
+fn insert_and_get() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = CacheDir::open(dir.path()).unwrap();
+
+    assert!(cache.get("artifact").unwrap().is_none());
+    cache.insert("artifact", "first").unwrap();
+    cache.insert("artifact", "second!").unwrap();
+
+    let mut entry = cache.get("artifact").unwrap().unwrap();
+    assert_eq!(entry.key(), "artifact");
+    assert_eq!(entry.read_all().unwrap(), b"second!");
+    assert_eq!(cache.size().unwrap(), 7);
+}
+
+#[test]
+fn gc_evicts_least_recently_used_and_skips_readers() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = CacheDir::open(dir.path()).unwrap();
+    // Last use is the modification time, which the kernel only updates every few milliseconds.
+    let tick = || std::thread::sleep(std::time::Duration::from_millis(30));
+    for key in ["a", "b", "c", "d"] {
+        cache.insert(key, [0u8; 100]).unwrap();
+        tick();
+    }
+
+    // "a" becomes the most recently used and "b" is being read.
+    drop(cache.get("a").unwrap().unwrap());
+    tick();
+    let reading = cache.get("b").unwrap().unwrap();
+
+    assert_eq!(cache.gc(200).unwrap(), 200);
+    assert!(cache.get("a").unwrap().is_some());
+    assert!(cache.get("b").unwrap().is_some());
+    assert!(cache.get("c").unwrap().is_none());
+    assert!(cache.get("d").unwrap().is_none());
+    assert_eq!(cache.size().unwrap(), 200);
+
+    drop(reading);
+    assert_eq!(cache.gc(0).unwrap(), 200);
+    assert_eq!(cache.size().unwrap(), 0);
+}
+
+#[test]
+fn incomplete_entries_are_absent_and_collected() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = CacheDir::open(dir.path()).unwrap();
+    std::fs::write(dir.path().join("partial"), "half").unwrap();
+
+    assert!(cache.get("partial").unwrap().is_none());
+    assert_eq!(cache.gc(1000).unwrap(), 0);
+    assert!(!dir.path().join("partial").exists());
+}
+
+#[test]
+fn gc_drops_records_of_missing_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache = CacheDir::open(dir.path()).unwrap();
+    cache.insert("gone", [0u8; 100]).unwrap();
+    cache.insert("kept", [0u8; 10]).unwrap();
+    std::fs::remove_file(dir.path().join("gone")).unwrap();
+
+    assert!(cache.get("gone").unwrap().is_none());
+    assert_eq!(cache.gc(1000).unwrap(), 0);
+    assert_eq!(cache.size().unwrap(), 10);
+    assert!(cache.get("kept").unwrap().is_some());
+}
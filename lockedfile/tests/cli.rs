@@ -0,0 +1,132 @@
+mod common;
+
+use std::process::Command;
+use lockedfile::LockMode;
+use lockedfile::std::OpenOptions;
+use common::create_temp_path;
+
+fn lockedfile() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_lockedfile"))
+}
+
+#[test]
+fn exec_propagates_exit_status() {
+    let path = create_temp_path();
+    let status = lockedfile()
+        .args(["exec", "--exclusive"]).arg(&path)
+        .args(["--", "sh", "-c", "exit 3"])
+        .status().unwrap();
+    assert_eq!(status.code(), Some(3));
+
+    let status = lockedfile()
+        .arg("exec").arg(&path)
+        .args(["--", "/nonexistent/command"])
+        .output().unwrap().status;
+    assert_eq!(status.code(), Some(127));
+}
+
+#[test]
+fn exec_holds_the_lock_while_the_command_runs() {
+    let path = create_temp_path();
+    let mut child = lockedfile()
+        .arg("exec").arg(&path)
+        .args(["--", "sleep", "1"])
+        .spawn().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert!(OpenOptions::new().read(true).try_open_with(&path, LockMode::Shared).unwrap().is_none());
+    assert!(child.wait().unwrap().success());
+    assert!(OpenOptions::new().read(true).try_open_with(&path, LockMode::Exclusive).unwrap().is_some());
+}
+
+#[test]
+fn exec_nonblock_and_timeout_give_up() {
+    let path = create_temp_path();
+    let _held = OpenOptions::new().read(true).open_with(&path, LockMode::Exclusive).unwrap();
+
+    let output = lockedfile()
+        .args(["exec", "--nonblock"]).arg(&path)
+        .args(["--", "true"])
+        .output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = lockedfile()
+        .args(["exec", "--shared", "--timeout", "100ms"]).arg(&path)
+        .args(["--", "true"])
+        .output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("timed out"));
+
+    let output = lockedfile()
+        .args(["exec", "--nonblock", "--conflict-exit-code", "75"]).arg(&path)
+        .args(["--", "true"])
+        .output().unwrap();
+    assert_eq!(output.status.code(), Some(75));
+}
+
+#[test]
+fn exec_lock_outlives_a_killed_wrapper() {
+    let path = create_temp_path();
+    let mut wrapper = lockedfile()
+        .arg("exec").arg(&path)
+        .args(["--", "sleep", "2"])
+        .spawn().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    wrapper.kill().unwrap();
+    wrapper.wait().unwrap();
+
+    // The orphaned command still holds the lock until it exits.
+    let status = lockedfile()
+        .args(["exec", "--nonblock"]).arg(&path)
+        .args(["--", "true"])
+        .output().unwrap().status;
+    assert_eq!(status.code(), Some(1));
+
+    let status = lockedfile()
+        .args(["exec", "--timeout", "10s"]).arg(&path)
+        .args(["--", "true"])
+        .status().unwrap();
+    assert!(status.success());
+}
+
+#[test]
+fn exec_rejects_bad_arguments() {
+    let status = lockedfile().args(["exec", "--timeout", "soon", "lock", "--", "true"]).output().unwrap().status;
+    assert_eq!(status.code(), Some(64));
+
+    let status = lockedfile().args(["exec", "lock"]).output().unwrap().status;
+    assert_eq!(status.code(), Some(64));
+}
+
+#[test]
+fn status_and_who_report_holders() {
+    let path = create_temp_path();
+    let output = lockedfile().args(["status", "--json"]).arg(&path).output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"locked\":false,\"mode\":null"));
+
+    let _held = OpenOptions::new().read(true).open_with(&path, LockMode::Exclusive).unwrap();
+    let output = lockedfile().arg("status").arg(&path).output().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains("locked exclusive (1 holding, 0 waiting)"));
+
+    let output = lockedfile().args(["who", "--json"]).arg(&path).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains(&format!("\"pid\":{},\"mode\":\"exclusive\"", std::process::id())), "{}", stdout);
+    assert!(stdout.contains("\"waiting\":false,\"command\":\""), "{}", stdout);
+}
+
+#[test]
+fn wait_returns_once_the_lock_is_released() {
+    let path = create_temp_path();
+    let held = OpenOptions::new().read(true).open_with(&path, LockMode::Exclusive).unwrap();
+
+    let waiter = lockedfile().args(["wait", "--shared", "--json"]).arg(&path)
+        .stdout(std::process::Stdio::piped())
+        .spawn().unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    drop(held);
+
+    let output = waiter.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("\"mode\":\"shared\""));
+    assert!(OpenOptions::new().read(true).try_open_with(&path, LockMode::Exclusive).unwrap().is_some());
+}
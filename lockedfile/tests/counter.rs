@@ -0,0 +1,53 @@
+use lockedfile::Counter;
+
+#[test]
+fn counts_from_one() {
+    let dir = tempfile::tempdir().unwrap();
+    let counter = Counter::open(dir.path().join("build-number")).unwrap();
+
+    assert_eq!(counter.get().unwrap(), 0);
+    assert_eq!(counter.next().unwrap(), 1);
+    assert_eq!(counter.next().unwrap(), 2);
+    assert_eq!(counter.add(10).unwrap(), 12);
+    assert_eq!(counter.reserve(3).unwrap(), 13..16);
+    assert_eq!(counter.get().unwrap(), 15);
+
+    assert_eq!(std::fs::read_to_string(counter.path()).unwrap(), "00000000000000000015\n");
+}
+
+#[test]
+fn concurrent_increments_are_unique() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("ids");
+    Counter::open(&path).unwrap();
+
+    let threads = (0..4).map(|_| {
+        let path = path.clone();
+        std::thread::spawn(move || {
+            let counter = Counter::open(&path).unwrap();
+            let mut ids = (0..25).map(|_| counter.next().unwrap()).collect::<Vec<_>>();
+            ids.extend(counter.reserve(25).unwrap());
+            ids
+        })
+    }).collect::<Vec<_>>();
+
+    let mut ids = threads.into_iter()
+        .flat_map(|thread| thread.join().unwrap())
+        .collect::<Vec<_>>();
+    ids.sort_unstable();
+    assert_eq!(ids, (1..=200).collect::<Vec<_>>());
+}
+
+#[test]
+fn rejects_overflow_and_garbage() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("counter");
+    let counter = Counter::open(&path).unwrap();
+
+    counter.add(u64::MAX - 1).unwrap();
+    assert_eq!(counter.next().unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(counter.get().unwrap(), u64::MAX - 1);
+
+    std::fs::write(&path, "garbage").unwrap();
+    assert_eq!(counter.get().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+}
@@ -0,0 +1,66 @@
+#![cfg(feature = "json")]
+
+use serde::{Deserialize, Serialize};
+use lockedfile::{Document, Format};
+
+#[derive(Debug,Default,PartialEq,Serialize,Deserialize)]
+struct State {
+    name: String,
+    count: u32,
+}
+
+#[test]
+fn store_and_load() {
+    let dir = tempfile::tempdir().unwrap();
+    let doc = Document::<State>::new(dir.path().join("state.json"), Format::Json);
+
+    assert_eq!(doc.load().unwrap_err().kind(), std::io::ErrorKind::NotFound);
+
+    let state = State { name: "build".to_owned(), count: 3 };
+    doc.store(&state).unwrap();
+    assert_eq!(doc.load().unwrap(), state);
+}
+
+#[test]
+fn concurrent_updates_are_not_lost() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state.json");
+
+    let threads = (0..4).map(|_| {
+        let path = path.clone();
+        std::thread::spawn(move || {
+            let doc = Document::<State>::new(&path, Format::Json);
+            for _ in 0..10 {
+                doc.update(|state| state.count += 1).unwrap();
+            }
+        })
+    }).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(Document::<State>::new(&path, Format::Json).load().unwrap().count, 40);
+}
+
+#[test]
+fn rejects_malformed_document() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("state.json");
+    std::fs::write(&path, "{").unwrap();
+
+    let err = Document::<State>::new(&path, Format::Json).load().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[cfg(all(feature = "toml", feature = "bincode"))]
+#[test]
+fn other_formats() {
+    let dir = tempfile::tempdir().unwrap();
+    let state = State { name: "cache".to_owned(), count: 7 };
+
+    for (name, format) in [("state.toml", Format::Toml), ("state.bin", Format::Bincode)] {
+        let doc = Document::<State>::new(dir.path().join(name), format);
+        doc.update(|current| *current = State { name: state.name.clone(), count: state.count }).unwrap();
+        assert_eq!(doc.load().unwrap(), state);
+    }
+}
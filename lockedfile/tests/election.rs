@@ -0,0 +1,86 @@
+use std::time::Duration;
+use lockedfile::std::{ElectionEvent, LeaderElection};
+
+#[test]
+fn one_leader_at_a_time() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("leader");
+    let first = LeaderElection::new(&path, "first");
+    let second = LeaderElection::new(&path, "second");
+
+    assert_eq!(LeaderElection::leader(&path).unwrap(), None);
+
+    let leadership = first.try_lead().unwrap().unwrap();
+    assert!(second.try_lead().unwrap().is_none());
+    assert_eq!(LeaderElection::leader(&path).unwrap().as_deref(), Some("first"));
+
+    drop(leadership);
+    assert_eq!(LeaderElection::leader(&path).unwrap(), None);
+    let _leadership = second.lead().unwrap();
+    assert_eq!(LeaderElection::leader(&path).unwrap().as_deref(), Some("second"));
+}
+
+#[test]
+fn campaign_gains_leadership_when_leader_exits() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("leader");
+    let leadership = LeaderElection::new(&path, "active").lead().unwrap();
+
+    let campaign = LeaderElection::new(&path, "standby").campaign(Duration::from_millis(10));
+    assert_eq!(campaign.recv_timeout(Duration::from_millis(50)), None);
+    assert!(!campaign.is_leader());
+
+    drop(leadership);
+    assert_eq!(campaign.recv_timeout(Duration::from_secs(5)), Some(ElectionEvent::Gained));
+    assert!(campaign.is_leader());
+    assert_eq!(LeaderElection::leader(&path).unwrap().as_deref(), Some("standby"));
+
+    drop(campaign);
+    assert_eq!(LeaderElection::leader(&path).unwrap(), None);
+}
+
+#[test]
+fn campaign_notices_replaced_election_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("leader");
+
+    let campaign = LeaderElection::new(&path, "standby").campaign(Duration::from_millis(10));
+    assert_eq!(campaign.recv_timeout(Duration::from_secs(5)), Some(ElectionEvent::Gained));
+
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(campaign.recv_timeout(Duration::from_secs(5)), Some(ElectionEvent::Lost));
+    assert_eq!(campaign.recv_timeout(Duration::from_secs(5)), Some(ElectionEvent::Gained));
+}
+
+#[test]
+fn campaign_takes_over_promptly_and_stops_while_waiting() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("leader");
+    let leadership = LeaderElection::new(&path, "active").lead().unwrap();
+
+    // Dropping a campaign that is still waiting does not wait for the leader.
+    drop(LeaderElection::new(&path, "stopped").campaign(Duration::from_secs(3600)));
+
+    let campaign = LeaderElection::new(&path, "standby").campaign(Duration::from_secs(3600));
+    drop(leadership);
+    assert_eq!(campaign.recv_timeout(Duration::from_secs(5)), Some(ElectionEvent::Gained));
+    assert_eq!(LeaderElection::leader(&path).unwrap().as_deref(), Some("standby"));
+}
+
+#[test]
+fn leader_reads_only_a_complete_identity() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("leader");
+    let _lock = lockedfile::std::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .open_exclusive(&path)
+        .unwrap();
+
+    // A new identity written over a longer one, before the file was cut to length.
+    std::fs::write(&path, "new\nious-identity\n").unwrap();
+    assert_eq!(LeaderElection::leader(&path).unwrap().as_deref(), Some("new"));
+
+    std::fs::write(&path, "partial").unwrap();
+    assert_eq!(LeaderElection::leader(&path).unwrap(), None);
+}
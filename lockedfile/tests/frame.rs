@@ -0,0 +1,48 @@
+use lockedfile::Corrupted;
+use lockedfile::std::{OwnedFile, SharedFile};
+
+fn corruption(err: std::io::Error) -> Corrupted {
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    err.into_inner().unwrap().downcast_ref::<Corrupted>().unwrap().clone()
+}
+
+#[test]
+fn framed_content_round_trips() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+
+    OwnedFile::write_framed(&path, "a longer first version").unwrap();
+    OwnedFile::write_framed(&path, "second").unwrap();
+    assert_eq!(SharedFile::read_framed(&path).unwrap(), b"second");
+}
+
+#[test]
+fn detects_truncated_content() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    OwnedFile::write_framed(&path, "hello world").unwrap();
+
+    let len = std::fs::metadata(&path).unwrap().len();
+    std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(len - 3).unwrap();
+
+    let err = SharedFile::read_framed(&path).unwrap_err();
+    assert_eq!(corruption(err).reason, "length mismatch");
+}
+
+#[test]
+fn detects_modified_content() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    OwnedFile::write_framed(&path, "hello world").unwrap();
+
+    let mut buf = std::fs::read(&path).unwrap();
+    *buf.last_mut().unwrap() ^= 1;
+    std::fs::write(&path, buf).unwrap();
+
+    let err = SharedFile::read_framed(&path).unwrap_err();
+    assert_eq!(corruption(err).reason, "checksum mismatch");
+
+    std::fs::write(&path, "hello world").unwrap();
+    let err = SharedFile::read_framed(&path).unwrap_err();
+    assert_eq!(corruption(err).reason, "missing header");
+}
@@ -0,0 +1,68 @@
+use std::io::Read;
+use lockedfile::std::{recover, JournaledFile, SharedFile};
+
+fn read_shared(path: &std::path::Path) -> String {
+    let mut buf = String::new();
+    SharedFile::open(path).unwrap().read_to_string(&mut buf).unwrap();
+    buf
+}
+
+/// Writes a journal the way `JournaledFile::commit` does before applying it.
+fn journal(records: &[(u64, &[u8])]) -> Vec<u8> {
+    let mut buf = b"LFJ1".to_vec();
+    buf.extend_from_slice(&(records.len() as u64).to_le_bytes());
+    for (offset, data) in records {
+        buf.extend_from_slice(&offset.to_le_bytes());
+        buf.extend_from_slice(&(data.len() as u64).to_le_bytes());
+        buf.extend_from_slice(data);
+    }
+    let checksum = buf.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    buf.extend_from_slice(&checksum.to_le_bytes());
+    buf
+}
+
+#[test]
+fn journaled_file_commits_in_place() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    std::fs::write(&path, "hello world").unwrap();
+
+    let mut file = JournaledFile::open(&path).unwrap();
+    assert!(!file.recovered());
+    file.write_at(0, b"HELLO")
+        .write_at(6, b"there");
+    file.commit().unwrap();
+    drop(file);
+
+    assert_eq!(read_shared(&path), "HELLO there");
+    assert_eq!(std::fs::metadata(dir.path().join("data.journal")).unwrap().len(), 0);
+}
+
+#[test]
+fn journaled_file_replays_complete_journal() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    std::fs::write(&path, "aaaaaaaa").unwrap();
+    std::fs::write(dir.path().join("data.journal"), journal(&[(2, b"bb"), (8, b"cc")])).unwrap();
+
+    let file = JournaledFile::open(&path).unwrap();
+    assert!(file.recovered());
+    drop(file);
+
+    assert_eq!(read_shared(&path), "aabbaaaacc");
+}
+
+#[test]
+fn journaled_file_discards_torn_journal() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    std::fs::write(&path, "aaaaaaaa").unwrap();
+    let torn = journal(&[(0, b"bbbb")]);
+    std::fs::write(dir.path().join("data.journal"), &torn[..torn.len() - 3]).unwrap();
+
+    assert!(!recover(&path).unwrap());
+    assert_eq!(read_shared(&path), "aaaaaaaa");
+    assert_eq!(std::fs::metadata(dir.path().join("data.journal")).unwrap().len(), 0);
+}
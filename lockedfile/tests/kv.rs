@@ -0,0 +1,153 @@
+use std::time::Duration;
+use lockedfile::std::KvStore;
+
+#[test]
+fn get_put_delete() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = KvStore::open(dir.path()).unwrap();
+
+    assert_eq!(store.get("crates/serde 1.0").unwrap(), None);
+    store.put("crates/serde 1.0", "index entry").unwrap();
+    store.put("tokio", "other").unwrap();
+    assert_eq!(store.get("crates/serde 1.0").unwrap().unwrap(), b"index entry");
+
+    let mut keys = store.keys().unwrap();
+    keys.sort();
+    assert_eq!(keys, ["crates/serde 1.0", "tokio"]);
+
+    assert!(store.delete("tokio").unwrap());
+    assert!(!store.delete("tokio").unwrap());
+    assert_eq!(store.keys().unwrap(), ["crates/serde 1.0"]);
+}
+
+#[test]
+fn compare_and_swap_checks_current_value() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = KvStore::open(dir.path()).unwrap();
+
+    assert!(store.compare_and_swap("key", None, Some(b"one")).unwrap());
+    assert!(!store.compare_and_swap("key", None, Some(b"two")).unwrap());
+    assert!(store.compare_and_swap("key", Some(b"one"), Some(b"two")).unwrap());
+    assert!(!store.compare_and_swap("key", Some(b"one"), None).unwrap());
+    assert!(store.compare_and_swap("key", Some(b"two"), None).unwrap());
+    assert_eq!(store.get("key").unwrap(), None);
+}
+
+#[test]
+fn expired_entries_read_as_absent() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = KvStore::open(dir.path()).unwrap();
+
+    store.put_with_ttl("short", "value", Duration::from_millis(20)).unwrap();
+    store.put_with_ttl("long", "value", Duration::from_secs(60)).unwrap();
+    assert!(store.get("short").unwrap().is_some());
+
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(store.get("short").unwrap(), None);
+    assert!(store.compare_and_swap("short", None, Some(b"fresh")).unwrap());
+    store.put_with_ttl("short", "value", Duration::from_millis(0)).unwrap();
+
+    assert_eq!(store.purge().unwrap(), 1);
+    assert_eq!(store.keys().unwrap(), ["long"]);
+}
+
+#[test]
+fn concurrent_compare_and_swap_increments() {
+    let dir = tempfile::tempdir().unwrap();
+    KvStore::open(dir.path()).unwrap().put("n", "0").unwrap();
+
+    let threads = (0..4).map(|_| {
+        let dir = dir.path().to_path_buf();
+        std::thread::spawn(move || {
+            let store = KvStore::open(dir).unwrap();
+            for _ in 0..10 {
+                loop {
+                    let current = store.get("n").unwrap().unwrap();
+                    let next = (String::from_utf8_lossy(&current).parse::<u32>().unwrap() + 1).to_string();
+                    if store.compare_and_swap("n", Some(&current), Some(next.as_bytes())).unwrap() {
+                        break;
+                    }
+                }
+            }
+        })
+    }).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let store = KvStore::open(dir.path()).unwrap();
+    assert_eq!(store.get("n").unwrap().unwrap(), b"40");
+}
+
+#[test]
+fn delete_removes_the_lock_sidecar() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = KvStore::open(dir.path()).unwrap();
+    let files = || std::fs::read_dir(dir.path()).unwrap().count();
+
+    store.put("key", "value").unwrap();
+    assert_eq!(files(), 2);
+    assert!(store.delete("key").unwrap());
+    assert_eq!(files(), 0);
+
+    // Looking up or deleting absent keys leaves nothing behind either.
+    assert_eq!(store.get("key").unwrap(), None);
+    assert!(!store.delete("key").unwrap());
+    assert_eq!(files(), 0);
+
+    let threads = (0..4).map(|i| {
+        let dir = dir.path().to_path_buf();
+        std::thread::spawn(move || {
+            let store = KvStore::open(dir).unwrap();
+            for _ in 0..50 {
+                if i % 2 == 0 {
+                    store.put("shared", "value").unwrap();
+                } else {
+                    store.delete("shared").unwrap();
+                }
+                if let Some(value) = store.get("shared").unwrap() {
+                    assert_eq!(value, b"value");
+                }
+            }
+        })
+    }).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+fn long_keys_get_hashed_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = KvStore::open(dir.path()).unwrap();
+    let long = "k".repeat(300);
+    let other = format!("{}/other", long);
+    let escaped = "é".repeat(100);
+
+    store.put(&long, "one").unwrap();
+    store.put(&other, "two").unwrap();
+    store.put_with_ttl(&escaped, "three", Duration::from_secs(60)).unwrap();
+    assert_eq!(store.get(&long).unwrap().unwrap(), b"one");
+    assert_eq!(store.get(&other).unwrap().unwrap(), b"two");
+    assert_eq!(store.get(&escaped).unwrap().unwrap(), b"three");
+
+    let mut keys = store.keys().unwrap();
+    keys.sort();
+    assert_eq!(keys, [long.clone(), other.clone(), escaped.clone()]);
+
+    assert!(store.delete(&long).unwrap());
+    assert_eq!(store.get(&long).unwrap(), None);
+    assert_eq!(store.keys().unwrap().len(), 2);
+}
+
+#[test]
+fn purge_skips_entries_whose_sidecar_is_gone() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = KvStore::open(dir.path()).unwrap();
+    store.put("key", "value").unwrap();
+
+    // As if the entry was deleted between the directory scan and the lock.
+    std::fs::remove_file(dir.path().join("key.lock")).unwrap();
+    assert_eq!(store.purge().unwrap(), 0);
+    assert!(!dir.path().join("key.lock").exists());
+}
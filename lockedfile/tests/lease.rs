@@ -0,0 +1,56 @@
+use std::time::{Duration, SystemTime};
+use lockedfile::std::Lease;
+
+mod common;
+
+const TTL: Duration = Duration::from_millis(300);
+
+#[test]
+fn lease_is_exclusive_until_released() {
+    let path = common::create_temp_path();
+
+    let lease = Lease::try_acquire(&path, TTL).unwrap().unwrap();
+    assert!(lease.is_held());
+    assert!(Lease::try_acquire(&path, TTL).unwrap().is_none());
+
+    let holder = Lease::holder(&path).unwrap().unwrap();
+    assert_eq!(holder.owner, lease.owner());
+
+    lease.release().unwrap();
+    assert!(Lease::holder(&path).unwrap().is_none());
+    assert!(Lease::try_acquire(&path, TTL).unwrap().is_some());
+}
+
+#[test]
+fn lease_outlives_ttl_while_refreshed() {
+    let path = common::create_temp_path();
+
+    let lease = Lease::try_acquire(&path, TTL).unwrap().unwrap();
+    std::thread::sleep(TTL * 3);
+
+    assert!(lease.is_held());
+    assert!(Lease::try_acquire(&path, TTL).unwrap().is_none());
+}
+
+#[test]
+fn stale_lease_is_taken_over() {
+    let path = common::create_temp_path();
+    let stale = SystemTime::now() - TTL * 10;
+    let millis = stale.duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis();
+    std::fs::write(&path, format!("frozen-holder\n{}\n", millis)).unwrap();
+
+    let lease = Lease::try_acquire(&path, TTL).unwrap().unwrap();
+    assert!(lease.is_held());
+    assert_eq!(Lease::holder(&path).unwrap().unwrap().owner, lease.owner());
+}
+
+#[test]
+fn lease_is_lost_after_takeover() {
+    let path = common::create_temp_path();
+
+    let lease = Lease::try_acquire(&path, TTL).unwrap().unwrap();
+    std::fs::write(&path, "another-holder\n0\n").unwrap();
+    std::thread::sleep(TTL);
+
+    assert!(!lease.is_held());
+}
@@ -0,0 +1,75 @@
+#![cfg(feature = "lockdep")]
+
+use lockedfile::std::OpenOptions;
+
+mod common;
+
+fn lock(path: &std::path::Path) -> std::fs::File {
+    OpenOptions::new()
+        .read(true)
+        .open_exclusive(path)
+        .unwrap()
+}
+
+#[test]
+fn consistent_order_is_accepted() {
+    let a = common::create_temp_path();
+    let b = common::create_temp_path();
+
+    for _ in 0..2 {
+        let _a = lock(&a);
+        let _b = lock(&b);
+    }
+}
+
+#[test]
+#[should_panic(expected = "lock order inversion")]
+fn inverted_order_across_threads_panics() {
+    let a = common::create_temp_path();
+    let b = common::create_temp_path();
+
+    {
+        let (a, b) = (a.to_path_buf(), b.to_path_buf());
+        std::thread::spawn(move || {
+            let _a = lock(&a);
+            let _b = lock(&b);
+        }).join().unwrap();
+    }
+
+    let _b = lock(&b);
+    let _a = lock(&a);
+}
+
+#[test]
+fn closed_locks_are_forgotten_when_their_descriptor_is_reused() {
+    let a = common::create_temp_path();
+    let b = common::create_temp_path();
+
+    drop(lock(&a));
+    // Most likely gets the descriptor of the closed lock back, for the same file.
+    let _reopened = std::fs::File::open(&a).unwrap();
+    drop(lock(&b));
+
+    let _b = lock(&b);
+    let _a = lock(&a);
+}
+
+#[test]
+#[should_panic(expected = "lock order inversion")]
+fn inversion_is_reported_across_paths_to_the_same_file() {
+    let a = common::create_temp_path();
+    let b = common::create_temp_path();
+
+    // The same file as `a`, relative to the current directory.
+    let cwd = std::env::current_dir().unwrap();
+    let mut relative = cwd.components().skip(1).map(|_| "..").collect::<std::path::PathBuf>();
+    relative.push(a.strip_prefix("/").unwrap());
+
+    {
+        let _a = lock(&relative);
+        let _b = lock(&b);
+    }
+
+    let _b = lock(&b);
+    let _a = lock(&a);
+}
@@ -0,0 +1,101 @@
+use std::io::ErrorKind;
+use std::time::Duration;
+use lockedfile::LockMode;
+use lockedfile::std::{LockSet, OpenOptions};
+
+mod common;
+
+#[test]
+fn lock_set_holds_every_lock() {
+    let a = common::create_temp_path();
+    let b = common::create_temp_path();
+
+    let guard = LockSet::new()
+        .exclusive(&a)
+        .shared(&b)
+        .lock()
+        .unwrap();
+
+    assert_eq!(guard.len(), 2);
+    assert_eq!(guard.mode(&a), Some(LockMode::Exclusive));
+    assert_eq!(guard.mode(&b), Some(LockMode::Shared));
+    assert!(OpenOptions::new().read(true).try_open_shared(&a).unwrap().is_none());
+    assert!(OpenOptions::new().read(true).try_open_shared(&b).unwrap().is_some());
+    assert!(OpenOptions::new().read(true).try_open_exclusive(&b).unwrap().is_none());
+
+    drop(guard);
+    assert!(OpenOptions::new().read(true).try_open_exclusive(&a).unwrap().is_some());
+}
+
+#[test]
+fn lock_set_merges_duplicate_paths() {
+    let a = common::create_temp_path();
+
+    let guard = LockSet::new()
+        .shared(&a)
+        .exclusive(&a)
+        .lock()
+        .unwrap();
+
+    assert_eq!(guard.len(), 1);
+    assert_eq!(guard.mode(&a), Some(LockMode::Exclusive));
+}
+
+#[test]
+fn lock_set_releases_on_timeout() {
+    let a = common::create_temp_path();
+    let b = common::create_temp_path();
+    let _held = OpenOptions::new().read(true).open_exclusive(&b).unwrap();
+
+    let err = LockSet::new()
+        .exclusive(&a)
+        .exclusive(&b)
+        .timeout(Duration::from_millis(50))
+        .lock()
+        .err()
+        .unwrap();
+
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(OpenOptions::new().read(true).try_open_exclusive(&a).unwrap().is_some());
+}
+
+#[test]
+fn lock_set_creates_missing_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("entry.lock");
+
+    let guard = LockSet::new()
+        .create(true)
+        .shared(&path)
+        .lock()
+        .unwrap();
+
+    assert!(guard.get(&path).is_some());
+}
+
+#[test]
+fn lock_set_relocks_a_path_replaced_while_waiting() {
+    use std::os::unix::fs::MetadataExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("entry");
+    std::fs::write(&path, "old").unwrap();
+    let held = OpenOptions::new().read(true).open_exclusive(&path).unwrap();
+
+    let waiter = {
+        let path = path.clone();
+        std::thread::spawn(move || {
+            let guard = LockSet::new().exclusive(&path).lock().unwrap();
+            guard.get(&path).unwrap().metadata().unwrap().ino()
+        })
+    };
+    std::thread::sleep(Duration::from_millis(200));
+
+    // The waiter opened the old file and gets its lock only after the path names another one.
+    let replacement = dir.path().join("replacement");
+    std::fs::write(&replacement, "new").unwrap();
+    std::fs::rename(&replacement, &path).unwrap();
+    drop(held);
+
+    assert_eq!(waiter.join().unwrap(), std::fs::metadata(&path).unwrap().ino());
+}
@@ -0,0 +1,44 @@
+#![cfg(feature = "memmap")]
+
+use lockedfile::std::{OwnedFile, SharedFile};
+
+#[test]
+fn map_under_shared_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("index");
+    std::fs::write(&path, "large index").unwrap();
+
+    let mut guard = SharedFile::lock(&path).unwrap();
+    let map = guard.map().unwrap();
+    assert_eq!(&map[..], b"large index");
+
+    let mut other = SharedFile::lock(&path).unwrap();
+    assert_eq!(&other.map().unwrap()[..5], b"large");
+}
+
+#[test]
+fn map_mut_under_exclusive_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("index");
+    std::fs::write(&path, "large index").unwrap();
+
+    let mut guard = OwnedFile::lock(&path).unwrap();
+    let mut map = guard.map_mut().unwrap();
+    map[..5].copy_from_slice(b"small");
+    map.flush().unwrap();
+    drop(map);
+    drop(guard);
+
+    assert_eq!(std::fs::read(&path).unwrap(), b"small index");
+}
+
+#[test]
+fn map_mut_needs_exclusive_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("index");
+    std::fs::write(&path, "large index").unwrap();
+
+    let mut guard = SharedFile::lock(&path).unwrap();
+    let err = guard.map_mut().err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+}
@@ -0,0 +1,92 @@
+use std::io::{Read, Write};
+use lockedfile::LockMode;
+use lockedfile::std::{OpenOptions, Poisoned};
+
+fn options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create(true).poison_on_crash(true);
+    options
+}
+
+fn poison(path: &std::path::Path) {
+    let path = path.to_path_buf();
+    let result = std::thread::spawn(move || {
+        let mut guard = options().lock(&path, LockMode::Exclusive).unwrap();
+        guard.write_all(b"half").unwrap();
+        panic!("writer died");
+    }).join();
+    assert!(result.is_err());
+}
+
+#[test]
+fn clean_release_does_not_poison() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+
+    let mut guard = options().lock(&path, LockMode::Exclusive).unwrap();
+    assert!(dir.path().join("data.poison").exists());
+    guard.write_all(b"done").unwrap();
+    guard.unlock().unwrap();
+
+    assert!(!dir.path().join("data.poison").exists());
+    options().lock(&path, LockMode::Shared).unwrap();
+    options().lock(&path, LockMode::Exclusive).unwrap();
+}
+
+#[test]
+fn panicking_writer_poisons_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    poison(&path);
+
+    let err = options().lock(&path, LockMode::Shared).unwrap_err();
+    let mut guard = Poisoned::from_io_error(err).unwrap().into_inner();
+    let mut buf = String::new();
+    guard.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "half");
+    drop(guard);
+
+    // Ignoring the error does not recover the file.
+    let err = options().try_lock(&path, LockMode::Exclusive).unwrap_err();
+    drop(Poisoned::from_io_error(err).unwrap());
+    assert!(options().lock(&path, LockMode::Exclusive).is_err());
+
+    // Unpoisoned options do not look at the marker.
+    OpenOptions::new().read(true).lock(&path, LockMode::Shared).unwrap();
+}
+
+#[test]
+fn recovered_file_is_no_longer_poisoned() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+    poison(&path);
+
+    let err = options().lock(&path, LockMode::Exclusive).unwrap_err();
+    let mut guard = Poisoned::from_io_error(err).unwrap().into_inner();
+    guard.set_len(0).unwrap();
+    guard.clear_poison();
+    drop(guard);
+
+    assert!(!dir.path().join("data.poison").exists());
+    options().lock(&path, LockMode::Exclusive).unwrap();
+}
+
+#[test]
+fn other_errors_are_passed_through() {
+    let err = std::io::Error::from(std::io::ErrorKind::NotFound);
+    let err = Poisoned::from_io_error(err).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn into_inner_leaves_the_file_poisoned() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data");
+
+    let file = options().lock(&path, LockMode::Exclusive).unwrap().into_inner();
+    drop(file);
+
+    assert!(dir.path().join("data.poison").exists());
+    let err = options().lock(&path, LockMode::Exclusive).unwrap_err();
+    assert!(Poisoned::from_io_error(err).is_ok());
+}
@@ -0,0 +1,32 @@
+use lockedfile::std::OpenOptions;
+use lockedfile::{Support, UnreliableLock};
+
+mod common;
+
+#[test]
+fn probe_temp_dir() {
+    let dir = std::env::temp_dir();
+    let caps = lockedfile::probe(&dir).unwrap();
+
+    assert_eq!(caps.flock, Support::Full);
+    assert!(caps.is_reliable());
+    assert!(!caps.filesystem.is_empty());
+}
+
+#[test]
+fn probe_leaves_no_scratch_file() {
+    let dir = tempfile::tempdir().unwrap();
+    lockedfile::probe(dir.path()).unwrap();
+
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn refuse_accepts_reliable_filesystem() {
+    let path = common::create_temp_path();
+    OpenOptions::new()
+        .write(true)
+        .on_unreliable_lock(UnreliableLock::Refuse)
+        .open_exclusive(&path)
+        .unwrap();
+}
@@ -0,0 +1,90 @@
+use std::time::Duration;
+use lockedfile::Queue;
+
+#[test]
+fn messages_are_claimed_in_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let queue = Queue::open(dir.path()).unwrap();
+    queue.publish("first").unwrap();
+    queue.publish("second").unwrap();
+    assert_eq!(queue.len().unwrap(), 2);
+
+    let mut claim = queue.claim().unwrap().unwrap();
+    assert_eq!(claim.content().unwrap(), b"first");
+    claim.complete().unwrap();
+
+    let mut claim = queue.claim().unwrap().unwrap();
+    assert_eq!(claim.content().unwrap(), b"second");
+    claim.complete().unwrap();
+
+    assert!(queue.claim().unwrap().is_none());
+    assert!(queue.is_empty().unwrap());
+}
+
+#[test]
+fn claimed_message_is_skipped_until_released() {
+    let dir = tempfile::tempdir().unwrap();
+    let queue = Queue::open(dir.path()).unwrap();
+    queue.publish("only").unwrap();
+
+    let claim = queue.claim().unwrap().unwrap();
+    assert!(queue.claim().unwrap().is_none());
+    assert!(queue.claim_timeout(Duration::from_millis(20)).unwrap().is_none());
+
+    drop(claim);
+    let mut claim = queue.claim().unwrap().unwrap();
+    assert_eq!(claim.content().unwrap(), b"only");
+}
+
+#[test]
+fn concurrent_consumers_process_each_message_once() {
+    let dir = tempfile::tempdir().unwrap();
+    let queue = Queue::open(dir.path()).unwrap();
+    for i in 0..100 {
+        queue.publish(i.to_string()).unwrap();
+    }
+
+    let threads = (0..4).map(|_| {
+        let dir = dir.path().to_path_buf();
+        std::thread::spawn(move || {
+            let queue = Queue::open(dir).unwrap();
+            let mut seen = Vec::new();
+            while let Some(mut claim) = queue.claim().unwrap() {
+                seen.push(String::from_utf8(claim.content().unwrap()).unwrap());
+                claim.complete().unwrap();
+            }
+            seen
+        })
+    }).collect::<Vec<_>>();
+
+    let mut seen = threads.into_iter()
+        .flat_map(|thread| thread.join().unwrap())
+        .map(|s| s.parse::<u32>().unwrap())
+        .collect::<Vec<_>>();
+    seen.sort_unstable();
+    assert_eq!(seen, (0..100).collect::<Vec<_>>());
+}
+
+#[test]
+fn claim_removes_temp_files_of_crashed_producers() {
+    let dir = tempfile::tempdir().unwrap();
+    let queue = Queue::open(dir.path()).unwrap();
+    let old = std::time::SystemTime::now() - Duration::from_secs(3600);
+    let temp = |name: &str, modified| {
+        std::fs::File::create(dir.path().join(name)).unwrap().set_modified(modified).unwrap();
+    };
+
+    temp(".tmp-crashed", old);
+    temp(".tmp-recent", std::time::SystemTime::now());
+    // A producer that is still writing holds a lock on its file.
+    temp(".tmp-writing", old);
+    let _writing = lockedfile::std::OpenOptions::new()
+        .read(true)
+        .open_exclusive(dir.path().join(".tmp-writing"))
+        .unwrap();
+
+    assert!(queue.claim().unwrap().is_none());
+    assert!(!dir.path().join(".tmp-crashed").exists());
+    assert!(dir.path().join(".tmp-recent").exists());
+    assert!(dir.path().join(".tmp-writing").exists());
+}
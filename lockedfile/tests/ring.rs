@@ -0,0 +1,92 @@
+#![cfg(feature = "memmap")]
+
+use lockedfile::std::{RingBuffer, RingReader};
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn drain(reader: &mut RingReader) -> Vec<Vec<u8>> {
+    std::iter::from_fn(|| reader.next_record().unwrap()).collect()
+}
+
+#[test]
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn ring_buffers_are_refused_without_ofd_locks() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("telemetry");
+    let err = RingBuffer::create(&path, 1024).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    let err = RingReader::open(&path).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn readers_see_records_pushed_after_they_open() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("telemetry");
+    let mut ring = RingBuffer::create(&path, 1024).unwrap();
+    ring.push("before").unwrap();
+
+    let mut reader = RingReader::open(&path).unwrap();
+    assert!(reader.next_record().unwrap().is_none());
+    ring.push("one").unwrap();
+    ring.push("two").unwrap();
+    assert_eq!(drain(&mut reader), [b"one".to_vec(), b"two".to_vec()]);
+
+    reader.seek_oldest().unwrap();
+    assert_eq!(drain(&mut reader).len(), 3);
+    assert_eq!(RingBuffer::open(&path).unwrap().capacity(), 1024);
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn oldest_records_are_overwritten_and_wrap_around() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("telemetry");
+    let mut ring = RingBuffer::create(&path, 33).unwrap();
+    let mut reader = RingReader::open(&path).unwrap();
+
+    // Records take 4 + 7 bytes, so only three fit.
+    for i in 0..5 {
+        ring.push(format!("record{}", i)).unwrap();
+    }
+
+    let expected = (2..5).map(|i| format!("record{}", i).into_bytes()).collect::<Vec<_>>();
+    assert_eq!(drain(&mut reader), expected);
+    assert_eq!(reader.skipped(), 22);
+
+    let err = ring.push([0u8; 30]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn concurrent_writers_and_reader() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("telemetry");
+    RingBuffer::create(&path, 1 << 16).unwrap();
+    let mut reader = RingReader::open(&path).unwrap();
+
+    let threads = (0..4u8).map(|i| {
+        let path = path.clone();
+        std::thread::spawn(move || {
+            let mut ring = RingBuffer::open(&path).unwrap();
+            for _ in 0..100 {
+                ring.push(vec![i; 100]).unwrap();
+            }
+        })
+    }).collect::<Vec<_>>();
+
+    let mut records = Vec::new();
+    while records.len() < 400 {
+        match reader.next_record().unwrap() {
+            Some(record) => records.push(record),
+            None => std::thread::yield_now(),
+        }
+    }
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(reader.skipped(), 0);
+    assert!(records.iter().all(|record| record.len() == 100 && record.iter().all(|&b| b == record[0])));
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+use lockedfile::std::Rotation;
+
+fn read(path: &std::path::Path) -> String {
+    std::fs::read_to_string(path).unwrap_or_default()
+}
+
+#[test]
+fn rotates_by_size_and_keeps_older_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let mut log = Rotation::new().max_size(10).keep(2).open(&path).unwrap();
+
+    for line in ["one\n", "two\n", "three\n", "four\n", "five\n", "six\n"] {
+        log.append(line.as_bytes()).unwrap();
+    }
+
+    assert_eq!(read(&path), "six\n");
+    assert_eq!(read(&dir.path().join("app.log.1")), "four\nfive\n");
+    assert_eq!(read(&dir.path().join("app.log.2")), "three\n");
+    assert!(!dir.path().join("app.log.3").exists());
+}
+
+#[test]
+fn rotates_by_age() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let mut log = Rotation::new().max_age(Duration::from_millis(50)).open(&path).unwrap();
+
+    log.append(b"old\n").unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    log.append(b"new\n").unwrap();
+    log.append(b"newer\n").unwrap();
+
+    assert_eq!(read(&dir.path().join("app.log.1")), "old\n");
+    assert_eq!(read(&path), "new\nnewer\n");
+}
+
+#[test]
+fn writers_follow_rotation_by_others() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("app.log");
+    let mut rotation = Rotation::new();
+    rotation.max_size(500);
+
+    let threads = (0..4).map(|i| {
+        let path = path.clone();
+        let rotation = rotation.clone();
+        std::thread::spawn(move || {
+            let mut log = rotation.open(&path).unwrap();
+            for j in 0..50 {
+                log.append(format!("{} {:02}\n", i, j).as_bytes()).unwrap();
+            }
+        })
+    }).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    // 200 lines of 5 bytes fill exactly two files of 500 bytes.
+    let lines = read(&dir.path().join("app.log.1")) + &read(&path);
+    let mut lines = lines.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 200);
+    lines.sort_unstable();
+    lines.dedup();
+    assert_eq!(lines.len(), 200);
+}
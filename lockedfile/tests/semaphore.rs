@@ -0,0 +1,56 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use lockedfile::Semaphore;
+
+#[test]
+fn permits_are_limited() {
+    let dir = tempfile::tempdir().unwrap();
+    let semaphore = Semaphore::open(dir.path(), 2).unwrap();
+
+    let first = semaphore.try_acquire().unwrap().unwrap();
+    let second = semaphore.acquire().unwrap();
+    assert_ne!(first.slot(), second.slot());
+    assert!(semaphore.try_acquire().unwrap().is_none());
+
+    let err = semaphore.acquire_timeout(Duration::from_millis(20)).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+    drop(first);
+    assert!(semaphore.try_acquire().unwrap().is_some());
+}
+
+#[test]
+fn concurrent_holders_never_exceed_permits() {
+    let dir = tempfile::tempdir().unwrap();
+    let running = Arc::new(AtomicUsize::new(0));
+    let peak = Arc::new(AtomicUsize::new(0));
+
+    let threads = (0..8).map(|_| {
+        let dir = dir.path().to_path_buf();
+        let running = running.clone();
+        let peak = peak.clone();
+        std::thread::spawn(move || {
+            let semaphore = Semaphore::open(dir, 3).unwrap();
+            for _ in 0..5 {
+                let _permit = semaphore.acquire().unwrap();
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(2));
+                running.fetch_sub(1, Ordering::SeqCst);
+            }
+        })
+    }).collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert!(peak.load(Ordering::SeqCst) <= 3);
+}
+
+#[test]
+fn rejects_zero_permits() {
+    let dir = tempfile::tempdir().unwrap();
+    let err = Semaphore::open(dir.path(), 0).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
@@ -76,3 +76,37 @@ fn create_and_append_owned_file() {
         .unwrap();
     write_block(&mut file);
 }
+
+#[test]
+fn try_open_locked_file() {
+    let path = create_temp_file_with_content();
+    let _owned = lockedfile::std::OwnedFile::open(&path).unwrap();
+
+    let mut options = OpenOptions::new();
+    options.read(true);
+    assert!(options.try_open_shared(&path).unwrap().is_none());
+    assert!(options.try_open_exclusive(&path).unwrap().is_none());
+
+    let err = options
+        .open_timeout(&path, lockedfile::LockMode::Shared, std::time::Duration::from_millis(20))
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+}
+
+#[test]
+fn locked_file_syncs_on_unlock() {
+    let path = common::create_temp_path();
+    let mut guard = OpenOptions::new()
+        .write(true)
+        .sync_on_unlock(lockedfile::SyncPolicy::Data)
+        .sync_parent_on_unlock(true)
+        .lock(&path, lockedfile::LockMode::Exclusive)
+        .unwrap();
+    write_block(&mut guard);
+
+    assert!(OpenOptions::new().read(true).try_open_shared(&path).unwrap().is_none());
+    guard.unlock().unwrap();
+
+    let mut file = lockedfile::std::SharedFile::open(&path).unwrap();
+    read_block(&mut file);
+}
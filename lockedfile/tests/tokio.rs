@@ -0,0 +1,91 @@
+use std::time::Duration;
+use lockedfile::{LockMode, SyncPolicy};
+use lockedfile::tokio::{ElectionEvent, LeaderElection, LockSet, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+mod common;
+
+fn block_on<F: std::future::Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+#[test]
+fn locked_file_syncs_on_unlock() {
+    let path = common::create_temp_path();
+    block_on(async {
+        let mut guard = OpenOptions::new()
+            .write(true)
+            .sync_on_unlock(SyncPolicy::Data)
+            .sync_parent_on_unlock(true)
+            .lock(&path, LockMode::Exclusive)
+            .await
+            .unwrap();
+        guard.write_all(b"synced").await.unwrap();
+
+        assert!(OpenOptions::new().read(true).try_open_shared(&path).await.unwrap().is_none());
+        guard.unlock().await.unwrap();
+
+        assert!(OpenOptions::new().read(true).try_open_exclusive(&path).await.unwrap().is_some());
+    });
+    assert_eq!(std::fs::read(&path).unwrap(), b"synced");
+}
+
+#[test]
+fn dropped_guard_is_released_after_syncing() {
+    let path = common::create_temp_path();
+    block_on(async {
+        let mut guard = OpenOptions::new()
+            .write(true)
+            .sync_on_unlock(SyncPolicy::All)
+            .lock(&path, LockMode::Exclusive)
+            .await
+            .unwrap();
+        guard.write_all(b"dropped").await.unwrap();
+        drop(guard);
+
+        let guard = OpenOptions::new().read(true).lock(&path, LockMode::Shared).await.unwrap();
+        assert_eq!(tokio::fs::read(guard.path()).await.unwrap(), b"dropped");
+    });
+}
+
+#[test]
+fn lock_set_guard_syncs_on_unlock() {
+    let dir = tempfile::tempdir().unwrap();
+    let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+    block_on(async {
+        let mut guard = LockSet::new()
+            .exclusive(&a)
+            .shared(&b)
+            .create(true)
+            .sync_on_unlock(SyncPolicy::Data)
+            .lock()
+            .await
+            .unwrap();
+        guard.get_mut(&a).unwrap().write_all(b"synced").await.unwrap();
+        guard.unlock().await.unwrap();
+
+        let guard = LockSet::new().exclusive(&a).exclusive(&b).lock().await.unwrap();
+        assert_eq!(guard.len(), 2);
+    });
+    assert_eq!(std::fs::read(&a).unwrap(), b"synced");
+}
+
+#[test]
+fn campaign_takes_over_when_leader_exits() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("leader");
+    block_on(async {
+        let leadership = LeaderElection::new(&path, "active").lead().await.unwrap();
+        drop(LeaderElection::new(&path, "stopped").campaign(Duration::from_secs(3600)));
+
+        let mut campaign = LeaderElection::new(&path, "standby").campaign(Duration::from_secs(3600));
+        drop(leadership);
+        let event = tokio::time::timeout(Duration::from_secs(5), campaign.recv()).await.unwrap();
+        assert_eq!(event, Some(ElectionEvent::Gained));
+        assert_eq!(LeaderElection::leader(&path).await.unwrap().as_deref(), Some("standby"));
+    });
+}
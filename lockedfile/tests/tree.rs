@@ -0,0 +1,54 @@
+use lockedfile::LockMode;
+use lockedfile::std::LockTree;
+
+#[test]
+fn exclusive_node_conflicts_with_subtree() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = LockTree::new(dir.path()).unwrap();
+
+    let leaf = tree.lock("a/b/c", LockMode::Exclusive).unwrap();
+    assert!(tree.try_lock("a/b", LockMode::Exclusive).unwrap().is_none());
+    assert!(tree.try_lock("a/b", LockMode::Shared).unwrap().is_none());
+    assert!(tree.try_lock("a/b/c", LockMode::Shared).unwrap().is_none());
+    assert!(tree.try_lock("", LockMode::Exclusive).unwrap().is_none());
+    assert!(tree.try_lock("a/b/d", LockMode::Exclusive).unwrap().is_some());
+    assert!(tree.try_lock("a/e", LockMode::Shared).unwrap().is_some());
+
+    drop(leaf);
+    assert!(tree.try_lock("a/b", LockMode::Exclusive).unwrap().is_some());
+}
+
+#[test]
+fn shared_subtree_blocks_writers_inside() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = LockTree::new(dir.path()).unwrap();
+
+    let _subtree = tree.lock("a", LockMode::Shared).unwrap();
+    assert!(tree.try_lock("a", LockMode::Shared).unwrap().is_some());
+    assert!(tree.try_lock("a/b", LockMode::Shared).unwrap().is_some());
+    assert!(tree.try_lock("a/b", LockMode::Exclusive).unwrap().is_none());
+    assert!(tree.try_lock("x/y", LockMode::Exclusive).unwrap().is_some());
+}
+
+#[test]
+fn rejects_parent_components() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = LockTree::new(dir.path()).unwrap();
+
+    let err = tree.lock("a/../b", LockMode::Shared).err().unwrap();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn deep_nodes_fit_into_file_names() {
+    let dir = tempfile::tempdir().unwrap();
+    let tree = LockTree::new(dir.path()).unwrap();
+    let deep = vec!["component"; 40].join("/");
+
+    let guard = tree.lock(format!("{}/leaf", deep), LockMode::Exclusive).unwrap();
+    assert!(tree.try_lock(&deep, LockMode::Shared).unwrap().is_none());
+    assert!(tree.try_lock(format!("{}/other", deep), LockMode::Exclusive).unwrap().is_some());
+
+    drop(guard);
+    assert!(tree.try_lock(&deep, LockMode::Exclusive).unwrap().is_some());
+}
@@ -0,0 +1,50 @@
+use lockedfile::std::{OwnedFile, VersionedFile};
+
+#[test]
+fn compare_and_swap_bumps_generation() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = VersionedFile::new(dir.path().join("doc"));
+
+    let snapshot = file.snapshot().unwrap();
+    assert_eq!(snapshot.generation, 0);
+    assert!(snapshot.content.is_empty());
+
+    assert_eq!(file.compare_and_swap(0, "first").unwrap(), Some(1));
+    assert_eq!(file.compare_and_swap(1, "second").unwrap(), Some(2));
+
+    let snapshot = file.snapshot().unwrap();
+    assert_eq!(snapshot.generation, 2);
+    assert_eq!(snapshot.content, b"second");
+}
+
+#[test]
+fn compare_and_swap_rejects_stale_generation() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = VersionedFile::new(dir.path().join("doc"));
+    file.compare_and_swap(0, "base").unwrap();
+
+    let mine = file.snapshot().unwrap();
+    let theirs = file.snapshot().unwrap();
+    assert_eq!(file.compare_and_swap(theirs.generation, "theirs").unwrap(), Some(2));
+    assert_eq!(file.compare_and_swap(mine.generation, "mine").unwrap(), None);
+    assert_eq!(file.snapshot().unwrap().content, b"theirs");
+}
+
+#[test]
+fn snapshot_does_not_hold_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = VersionedFile::new(dir.path().join("doc"));
+    file.compare_and_swap(0, "content").unwrap();
+
+    let _snapshot = file.snapshot().unwrap();
+    OwnedFile::create(dir.path().join("doc.lock")).unwrap();
+}
+
+#[test]
+fn rejects_unversioned_file() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("doc"), "plain").unwrap();
+
+    let err = VersionedFile::new(dir.path().join("doc")).snapshot().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+}